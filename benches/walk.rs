@@ -0,0 +1,56 @@
+extern crate clap;
+extern crate criterion;
+extern crate ds;
+extern crate tempdir;
+use clap::App;
+use criterion::{criterion_group, criterion_main, Criterion};
+use ds::DSGroup;
+use std::fs;
+use tempdir::TempDir;
+
+/// A wide directory such as a mail spool next to a few ordinary ones
+fn generate() -> TempDir {
+    let tmp_dir = TempDir::new("dsbench").unwrap();
+    let spool = tmp_dir.path().join("spool");
+    fs::create_dir(&spool).unwrap();
+    for i in 0..20000 {
+        fs::write(spool.join(format!("msg{}", i)), b"x").unwrap();
+    }
+    for d in 0..10 {
+        let dir = tmp_dir.path().join(format!("dir{}", d));
+        fs::create_dir(&dir).unwrap();
+        for i in 0..500 {
+            fs::write(dir.join(format!("file{}", i)), b"xx").unwrap();
+        }
+    }
+    tmp_dir
+}
+
+fn scan(anchor: &str) {
+    let matches = App::new("DSbench").get_matches_from(vec!["ds"]);
+    let mut group = DSGroup::new();
+    group.progress.enabled = false;
+    group.calculate(&vec![anchor.to_string()], &matches);
+}
+
+fn walk(c: &mut Criterion) {
+    let tmp_dir = generate();
+    let anchor = tmp_dir.path().to_string_lossy().to_string();
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    ds::set_uring(false);
+    c.bench_function("default walker", |b| b.iter(|| scan(&anchor)));
+
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    {
+        ds::set_uring(true);
+        c.bench_function("io_uring walker", |b| b.iter(|| scan(&anchor)));
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(20);
+    targets = walk
+}
+criterion_main!(benches);
@@ -12,11 +12,11 @@ use std::os::linux::fs::MetadataExt;
 use std::os::windows::fs::MetadataExt;
 use std::path::Path;
 
-/// Original implementation
-/// The goal was to gain an understanding of Rust's Result and Option types
-/// while learning some filesystem operations and structures.  I postponed
-/// using recursion.
-///
+// Original implementation
+// The goal was to gain an understanding of Rust's Result and Option types
+// while learning some filesystem operations and structures.  I postponed
+// using recursion.
+//
 
 /// Traverse
 ///
@@ -27,14 +27,13 @@ pub fn traverse(anchor: &String, matches: &ArgMatches) -> BTreeMap<String, u64>
 
     directories.push(anchor.to_string());
 
-    while directories.len() != 0 {
-        let dir = directories.pop().unwrap();
+    while let Some(dir) = directories.pop() {
         if fs::symlink_metadata(&dir).unwrap().file_type().is_symlink() {
             continue;
         }
 
         match fs::read_dir(&dir) {
-            Ok(path) => process_path(anchor, path, &mut directories, &mut disk_space, &matches),
+            Ok(path) => process_path(anchor, path, &mut directories, &mut disk_space, matches),
             Err(err) => println!("Cannot read directory {} - {}", dir, err),
         }
     }
@@ -53,15 +52,12 @@ fn process_path(
     matches: &ArgMatches,
 ) {
     for entry in path.filter_map(|e| e.ok()) {
-        match entry.path().to_str() {
-            Some(pathname) => {
-                if entry.path().is_dir() {
-                    directories.push(pathname.to_string());
-                } else {
-                    process_file(anchor, entry, pathname.to_string(), disk_space, &matches)
-                }
+        if let Some(pathname) = entry.path().to_str() {
+            if entry.path().is_dir() {
+                directories.push(pathname.to_string());
+            } else {
+                process_file(anchor, entry, pathname.to_string(), disk_space, matches)
             }
-            None => (),
         }
     }
 }
@@ -79,19 +75,15 @@ fn process_file(
     match entry.metadata() {
         Ok(metadata) => {
             disk_space.insert(pathname, metadata.st_size());
-            match entry.path().parent() {
-                Some(parent) => match parent.to_str() {
-                    Some(parent_pathname) => {
-                        let path = Path::new(parent_pathname);
-                        if matches.occurrences_of("parent") > 0 {
-                            update_ancestors(top, path, &metadata, disk_space);
-                        } else {
-                            increment(parent_pathname, &metadata, disk_space);
-                        }
+            if let Some(parent) = entry.path().parent() {
+                if let Some(parent_pathname) = parent.to_str() {
+                    let path = Path::new(parent_pathname);
+                    if matches.occurrences_of("parent") > 0 {
+                        update_ancestors(top, path, &metadata, disk_space);
+                    } else {
+                        increment(parent_pathname, &metadata, disk_space);
                     }
-                    None => (),
-                },
-                None => (),
+                }
             }
         }
         Err(err) => println!("Skipping {} - {}", pathname, err),
@@ -109,7 +101,7 @@ fn update_ancestors(
 ) {
     for ancestor in path.ancestors() {
         let ancestor_path = ancestor.to_str().unwrap();
-        increment(ancestor_path, &metadata, disk_space);
+        increment(ancestor_path, metadata, disk_space);
         if top == ancestor_path {
             break;
         }
@@ -143,7 +135,7 @@ mod tests {
 
         let parent = String::from("path/to/file");
         let mut data = BTreeMap::new();
-        data.insert("path/to/file".to_string(), 0 as u64);
+        data.insert("path/to/file".to_string(), 0_u64);
 
         increment(&parent, &metadata, &mut data);
         fs::remove_file("foo.txt");
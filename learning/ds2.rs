@@ -10,10 +10,10 @@ use std::os::linux::fs::MetadataExt;
 use std::os::windows::fs::MetadataExt;
 use std::path::PathBuf;
 
-/// Second implementation
-/// Attempted to simplify the number of functions using recursion.  However,
-/// the double pass of building and then processing each file takes nearly
-/// twice the time as the original implementation.
+// Second implementation
+// Attempted to simplify the number of functions using recursion.  However,
+// the double pass of building and then processing each file takes nearly
+// twice the time as the original implementation.
 
 /// Discover
 /// Gather all filenames and process each
@@ -54,7 +54,7 @@ pub fn visit_dirs(dir: PathBuf, paths: &Vec<PathBuf>) -> Vec<PathBuf> {
             }
             if path.is_dir() {
                 pathnames.push(path.to_owned());
-                pathnames.extend(visit_dirs(path, &paths));
+                pathnames.extend(visit_dirs(path, paths));
             } else {
                 pathnames.push(path);
             }
@@ -10,10 +10,10 @@ use std::os::linux::fs::MetadataExt;
 use std::os::windows::fs::MetadataExt;
 use std::path::PathBuf;
 
-/// Third implementation
-/// Began experimenting with structures and implementations.  This one is
-/// a bit indirect since the BTreeMap is updated by visiting all the
-/// directories and files.  The result of ds.visit() is ignored.
+// Third implementation
+// Began experimenting with structures and implementations.  This one is
+// a bit indirect since the BTreeMap is updated by visiting all the
+// directories and files.  The result of ds.visit() is ignored.
 
 struct DiskSpace {
     disk_space: BTreeMap<String, u64>,
@@ -59,7 +59,7 @@ impl DiskSpace {
                 }
 
                 if path.is_dir() {
-                    self.visit(&path.to_string_lossy().to_string(), &paths);
+                    self.visit(&path.to_string_lossy().to_string(), paths);
                 } else {
                     self.update(dir, path.to_owned(), path.metadata().unwrap().st_size());
                 }
@@ -8,10 +8,10 @@ use std::os::windows::fs::MetadataExt;
 use std::path::PathBuf;
 use std::sync::Mutex;
 
-/// Fourth implementation
-/// For using recursion with simple functions, I found that Mutex is useful.
-/// This version became the base for the implementation in ds.rs.  This is
-/// the simplest so far.
+// Fourth implementation
+// For using recursion with simple functions, I found that Mutex is useful.
+// This version became the base for the implementation in ds.rs.  This is
+// the simplest so far.
 
 /// Traverse
 ///
@@ -56,7 +56,7 @@ pub fn visit_dirs(dir: PathBuf, mds: &mut Mutex<BTreeMap<String, u64>>) {
                 };
                 for ancestor in path.ancestors() {
                     let ancestor_path = ancestor.to_string_lossy().to_string();
-                    *mds.lock().unwrap().entry(ancestor_path).or_insert(0) += filesize;
+                    *mds.get_mut().unwrap().entry(ancestor_path).or_insert(0) += filesize;
                     if anchor == ancestor {
                         break;
                     }
@@ -10,17 +10,17 @@ use std::path::PathBuf;
 use std::sync::Mutex;
 use std::thread;
 
-/// Fifth implementation
-/// I had the suspicion that parallel processing some parts of the
-/// directory trees might help.  However, implementing threads in a
-/// recursive function caused a bit of anguish.  I settled for only
-/// creating a thread for directories of a certain st_size or greater.
-/// In other words, keep directories with a few hundred files or less in
-/// the main thread.
-///
-/// I abandoned this solution since experimenting saved one second on a
-/// 200G home directory.  Additionally, this version can hit the number of
-/// open files limit if the number of threads goes too high.
+// Fifth implementation
+// I had the suspicion that parallel processing some parts of the
+// directory trees might help.  However, implementing threads in a
+// recursive function caused a bit of anguish.  I settled for only
+// creating a thread for directories of a certain st_size or greater.
+// In other words, keep directories with a few hundred files or less in
+// the main thread.
+//
+// I abandoned this solution since experimenting saved one second on a
+// 200G home directory.  Additionally, this version can hit the number of
+// open files limit if the number of threads goes too high.
 
 /// Traverse
 ///
@@ -68,7 +68,7 @@ pub fn visit_dirs(dir: PathBuf, mds: &mut Mutex<BTreeMap<String, u64>>) {
                 let filesize = path.metadata().unwrap().st_size();
                 for ancestor in path.ancestors() {
                     let ancestor_path = ancestor.to_string_lossy().to_string();
-                    *mds.lock().unwrap().entry(ancestor_path).or_insert(0) += filesize;
+                    *mds.get_mut().unwrap().entry(ancestor_path).or_insert(0) += filesize;
                     if anchor == ancestor {
                         break;
                     }
@@ -78,7 +78,7 @@ pub fn visit_dirs(dir: PathBuf, mds: &mut Mutex<BTreeMap<String, u64>>) {
         for child in children {
             let subtree = child.join().unwrap();
             for (directory, size) in &*subtree.lock().unwrap() {
-                mds.lock().unwrap().insert(directory.to_string(), *size);
+                mds.get_mut().unwrap().insert(directory.to_string(), *size);
             }
         }
     }
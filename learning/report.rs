@@ -34,10 +34,10 @@ pub fn report_stream(
     };
 
     let section = if matches.occurrences_of("reverse") == 0 {
-        sorted.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
+        sorted.sort_by_key(|&(_, a)| std::cmp::Reverse(a));
         &sorted[0..end]
     } else {
-        sorted.sort_by(|&(_, a), &(_, b)| a.cmp(&b));
+        sorted.sort_by_key(|&(_, a)| a);
         &sorted[(sorted.len() - end)..]
     };
 
@@ -99,8 +99,8 @@ mod tests {
     #[test]
     fn report_short() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
 
         let mut out = Vec::new();
         let matches = App::new("DiskSpace").get_matches();
@@ -120,8 +120,8 @@ mod tests {
     #[test]
     fn report_short_reverse() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
 
         let mut out = Vec::new();
         let args = vec!["ds", "-r"];
@@ -152,27 +152,27 @@ mod tests {
     #[test]
     fn report_long() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
-        data.insert("path/to/fileC".to_string(), 1023 as u64);
-        data.insert("path/to/fileD".to_string(), 1022 as u64);
-        data.insert("path/to/fileE".to_string(), 1021 as u64);
-        data.insert("path/to/fileF".to_string(), 1020 as u64);
-        data.insert("path/to/fileG".to_string(), 1019 as u64);
-        data.insert("path/to/fileH".to_string(), 1018 as u64);
-        data.insert("path/to/fileI".to_string(), 1017 as u64);
-        data.insert("path/to/fileJ".to_string(), 1016 as u64);
-        data.insert("path/to/fileK".to_string(), 1015 as u64);
-        data.insert("path/to/fileL".to_string(), 1014 as u64);
-        data.insert("path/to/fileM".to_string(), 1013 as u64);
-        data.insert("path/to/fileN".to_string(), 1012 as u64);
-        data.insert("path/to/fileO".to_string(), 1011 as u64);
-        data.insert("path/to/fileP".to_string(), 1010 as u64);
-        data.insert("path/to/fileQ".to_string(), 1009 as u64);
-        data.insert("path/to/fileR".to_string(), 1008 as u64);
-        data.insert("path/to/fileS".to_string(), 1007 as u64);
-        data.insert("path/to/fileT".to_string(), 1006 as u64);
-        data.insert("path/to/fileU".to_string(), 1005 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
+        data.insert("path/to/fileC".to_string(), 1023_u64);
+        data.insert("path/to/fileD".to_string(), 1022_u64);
+        data.insert("path/to/fileE".to_string(), 1021_u64);
+        data.insert("path/to/fileF".to_string(), 1020_u64);
+        data.insert("path/to/fileG".to_string(), 1019_u64);
+        data.insert("path/to/fileH".to_string(), 1018_u64);
+        data.insert("path/to/fileI".to_string(), 1017_u64);
+        data.insert("path/to/fileJ".to_string(), 1016_u64);
+        data.insert("path/to/fileK".to_string(), 1015_u64);
+        data.insert("path/to/fileL".to_string(), 1014_u64);
+        data.insert("path/to/fileM".to_string(), 1013_u64);
+        data.insert("path/to/fileN".to_string(), 1012_u64);
+        data.insert("path/to/fileO".to_string(), 1011_u64);
+        data.insert("path/to/fileP".to_string(), 1010_u64);
+        data.insert("path/to/fileQ".to_string(), 1009_u64);
+        data.insert("path/to/fileR".to_string(), 1008_u64);
+        data.insert("path/to/fileS".to_string(), 1007_u64);
+        data.insert("path/to/fileT".to_string(), 1006_u64);
+        data.insert("path/to/fileU".to_string(), 1005_u64);
 
         let mut out = Vec::new();
         let matches = App::new("DiskSpace").get_matches();
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Marks the root of an archive's contents, as in backup.tar.gz!/var/log
+pub const ARCHIVE_SEPARATOR: &str = "!";
+
+/// ArchiveKind
+///
+/// Archive formats whose members are listed with --into-archives
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    Zip,
+}
+
+/// archive_kind
+///
+/// Recognize an archive by the extension of its name
+pub fn archive_kind(name: &OsStr) -> Option<ArchiveKind> {
+    let name = name.to_string_lossy().to_lowercase();
+    let kinds = [
+        (".tar", ArchiveKind::Tar),
+        (".tar.gz", ArchiveKind::TarGz),
+        (".tgz", ArchiveKind::TarGz),
+        (".tar.xz", ArchiveKind::TarXz),
+        (".txz", ArchiveKind::TarXz),
+        (".tar.zst", ArchiveKind::TarZst),
+        (".tar.zstd", ArchiveKind::TarZst),
+        (".tzst", ArchiveKind::TarZst),
+        (".zip", ArchiveKind::Zip),
+    ];
+    kinds
+        .iter()
+        .find(|(extension, _)| name.ends_with(extension) && name.len() > extension.len())
+        .map(|(_, kind)| *kind)
+}
+
+/// archive_root
+///
+/// The path under which the members of the archive at path are recorded
+pub fn archive_root(path: &Path) -> PathBuf {
+    let mut root = path.as_os_str().to_os_string();
+    root.push(ARCHIVE_SEPARATOR);
+    PathBuf::from(root)
+}
+
+/// is_archive_root
+///
+/// Check whether path is the root of an archive's contents
+pub fn is_archive_root(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .and_then(|name| name.strip_suffix(ARCHIVE_SEPARATOR).map(String::from))
+        .is_some_and(|name| archive_kind(OsStr::new(&name)).is_some())
+}
+
+/// Member
+///
+/// A regular file or directory stored in an archive.  The path is relative
+/// to the archive root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub path: PathBuf,
+    pub size: u64,
+    pub directory: bool,
+}
+
+/// members
+///
+/// List the regular files and directories of an archive.  Compressed tar
+/// archives are decompressed in a single pass; zip archives are read from
+/// the central directory.
+pub fn members(file: File, kind: ArchiveKind) -> io::Result<Vec<Member>> {
+    let reader = BufReader::new(file);
+    match kind {
+        ArchiveKind::Tar => tar_members(reader),
+        ArchiveKind::TarGz => tar_members(flate2::read::MultiGzDecoder::new(reader)),
+        ArchiveKind::TarXz => tar_members(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        ArchiveKind::TarZst => tar_members(zstd::stream::read::Decoder::with_buffer(reader)?),
+        ArchiveKind::Zip => zip_members(reader),
+    }
+}
+
+fn tar_members<R: Read>(reader: R) -> io::Result<Vec<Member>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut result = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let directory = match entry.header().entry_type() {
+            tar::EntryType::Regular | tar::EntryType::Continuous | tar::EntryType::GNUSparse => {
+                false
+            }
+            tar::EntryType::Directory => true,
+            _ => continue,
+        };
+        if let Some(path) = normalize(&entry.path()?) {
+            result.push(Member {
+                path,
+                size: if directory { 0 } else { entry.size() },
+                directory,
+            });
+        }
+    }
+    Ok(result)
+}
+
+fn zip_members<R: Read + io::Seek>(reader: R) -> io::Result<Vec<Member>> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(io::Error::other)?;
+    let mut result = vec![];
+    for index in 0..archive.len() {
+        let file = archive.by_index_raw(index).map_err(io::Error::other)?;
+        if let Some(path) = normalize(Path::new(file.name())) {
+            result.push(Member {
+                path,
+                size: file.size(),
+                directory: file.is_dir(),
+            });
+        }
+    }
+    Ok(result)
+}
+
+/// normalize
+///
+/// Drop leading ./ and / from a member path.  Members escaping the archive
+/// root with .. are ignored.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => result.push(name),
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+            Component::ParentDir => return None,
+        }
+    }
+    if result.as_os_str().is_empty() {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+/// tree
+///
+/// Arrange the members below root as the directories and file sizes of a
+/// traversal.  Each directory lists its children, files of size 0 are
+/// omitted from sizes.
+pub fn tree(
+    root: &Path,
+    members: &[Member],
+) -> (BTreeMap<PathBuf, BTreeSet<PathBuf>>, BTreeMap<PathBuf, u64>) {
+    let mut dirs: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+    let mut sizes = BTreeMap::new();
+    dirs.insert(root.to_path_buf(), BTreeSet::new());
+
+    for member in members {
+        let path = root.join(&member.path);
+        if member.directory {
+            dirs.entry(path.clone()).or_default();
+        } else if member.size > 0 {
+            sizes.insert(path.clone(), member.size);
+        }
+
+        let mut child = path;
+        while let Some(parent) = child.parent() {
+            let parent = parent.to_path_buf();
+            let known = dirs.contains_key(&parent);
+            dirs.entry(parent.clone()).or_default().insert(child);
+            if parent == root || known {
+                break;
+            }
+            child = parent;
+        }
+    }
+    (dirs, sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind_by_extension() {
+        assert_eq!(
+            archive_kind(OsStr::new("backup.tar.gz")),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(archive_kind(OsStr::new("a.TGZ")), Some(ArchiveKind::TarGz));
+        assert_eq!(archive_kind(OsStr::new("a.tar")), Some(ArchiveKind::Tar));
+        assert_eq!(archive_kind(OsStr::new("a.txz")), Some(ArchiveKind::TarXz));
+        assert_eq!(
+            archive_kind(OsStr::new("a.tar.zst")),
+            Some(ArchiveKind::TarZst)
+        );
+        assert_eq!(archive_kind(OsStr::new("a.zip")), Some(ArchiveKind::Zip));
+        assert_eq!(archive_kind(OsStr::new(".zip")), None);
+        assert_eq!(archive_kind(OsStr::new("a.gz")), None);
+    }
+
+    #[test]
+    fn archive_roots() {
+        assert_eq!(
+            archive_root(Path::new("/tmp/backup.tar.gz")),
+            PathBuf::from("/tmp/backup.tar.gz!")
+        );
+        assert!(is_archive_root(Path::new("/tmp/backup.tar.gz!")));
+        assert!(!is_archive_root(Path::new("/tmp/backup.tar.gz")));
+        assert!(!is_archive_root(Path::new("/tmp/wow!")));
+    }
+
+    #[test]
+    fn normalize_members() {
+        assert_eq!(
+            normalize(Path::new("./var/log/")),
+            Some(PathBuf::from("var/log"))
+        );
+        assert_eq!(
+            normalize(Path::new("/etc/hosts")),
+            Some(PathBuf::from("etc/hosts"))
+        );
+        assert_eq!(normalize(Path::new("../escape")), None);
+        assert_eq!(normalize(Path::new("./")), None);
+    }
+
+    #[test]
+    fn tree_of_members() {
+        let root = Path::new("/b.zip!");
+        let members = vec![
+            Member {
+                path: PathBuf::from("var/log/big.log"),
+                size: 50,
+                directory: false,
+            },
+            Member {
+                path: PathBuf::from("var/log/small.log"),
+                size: 5,
+                directory: false,
+            },
+            Member {
+                path: PathBuf::from("empty"),
+                size: 0,
+                directory: true,
+            },
+        ];
+        let (dirs, sizes) = tree(root, &members);
+
+        assert_eq!(dirs.len(), 4);
+        assert_eq!(dirs[root].len(), 2);
+        assert_eq!(dirs[Path::new("/b.zip!/var/log")].len(), 2);
+        assert!(dirs[Path::new("/b.zip!/empty")].is_empty());
+        assert_eq!(sizes[Path::new("/b.zip!/var/log/big.log")], 50);
+        assert_eq!(sizes.len(), 2);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::filesystem::FilesystemInfo;
+use crate::walk;
+use crate::walk::{Dir, DirEntry, Kind, Meta};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::linux::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// FsBackend
+///
+/// The source of directories and metadata that DSGroup traverses.  Anchors
+/// are opened by path; everything below is reached relative to an open
+/// directory, so a backend need not support paths at all below its anchors.
+pub trait FsBackend {
+    /// An open directory
+    type Dir;
+
+    /// Open an anchor directory
+    fn open(&self, path: &Path) -> io::Result<Self::Dir>;
+
+    /// Open a child directory of an open directory
+    fn open_at(&self, dir: &Self::Dir, entry: &DirEntry) -> io::Result<Self::Dir>;
+
+    /// Read the next entry of a directory, like readdir(3).  None at the end.
+    fn read_dir(&self, dir: &mut Self::Dir) -> Option<io::Result<DirEntry>>;
+
+    /// Metadata of an anchor, following symlinks
+    fn stat(&self, path: &Path) -> io::Result<Meta>;
+
+    /// Metadata of an open directory
+    fn dir_metadata(&self, dir: &Self::Dir) -> io::Result<Meta>;
+
+    /// Metadata of entries of an open directory, like lstat(2).  Symlinks are
+    /// not followed.  Backends may answer the entries together.
+    fn metadata(&self, dir: &Self::Dir, entries: &[DirEntry]) -> Vec<io::Result<Meta>>;
+
+    /// Device id of an anchor.  Returns 0 when unknown.
+    fn device(&self, path: &Path) -> u64;
+
+    /// Capacity of the filesystem holding path
+    fn filesystem(&self, path: &Path) -> io::Result<FilesystemInfo>;
+
+    /// Open a file of an open directory to read its contents
+    fn open_file(&self, _dir: &Self::Dir, _entry: &DirEntry) -> io::Result<File> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file contents are not available",
+        ))
+    }
+
+    /// Open an anchor that is a file to read its contents
+    fn open_anchor_file(&self, _path: &Path) -> io::Result<File> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file contents are not available",
+        ))
+    }
+}
+
+/// Local
+///
+/// The filesystems of the running system
+pub struct Local;
+
+impl FsBackend for Local {
+    type Dir = Dir;
+
+    fn open(&self, path: &Path) -> io::Result<Dir> {
+        Dir::open(path)
+    }
+
+    fn open_at(&self, dir: &Dir, entry: &DirEntry) -> io::Result<Dir> {
+        dir.open_at(entry)
+    }
+
+    fn read_dir(&self, dir: &mut Dir) -> Option<io::Result<DirEntry>> {
+        dir.next()
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Meta> {
+        walk::stat(path)
+    }
+
+    fn dir_metadata(&self, dir: &Dir) -> io::Result<Meta> {
+        dir.stat()
+    }
+
+    fn metadata(&self, dir: &Dir, entries: &[DirEntry]) -> Vec<io::Result<Meta>> {
+        dir.stat_batch(entries)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn device(&self, _path: &Path) -> u64 {
+        0
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn device(&self, path: &Path) -> u64 {
+        match path.metadata() {
+            Ok(metadata) => metadata.st_dev(),
+            Err(_) => 0,
+        }
+    }
+
+    fn filesystem(&self, path: &Path) -> io::Result<FilesystemInfo> {
+        FilesystemInfo::new(path)
+    }
+
+    fn open_file(&self, dir: &Dir, entry: &DirEntry) -> io::Result<File> {
+        dir.open_file_at(entry)
+    }
+
+    fn open_anchor_file(&self, path: &Path) -> io::Result<File> {
+        File::open(path)
+    }
+}
+
+/// MemoryFs
+///
+/// A tree of directories, files and symlinks held in memory, for tests and
+/// for listings fetched whole such as object stores.  Entries are added by
+/// path; missing parent directories are created.
+pub struct MemoryFs {
+    pub info: FilesystemInfo,
+    entries: BTreeMap<PathBuf, Meta>,
+    children: BTreeMap<PathBuf, BTreeSet<PathBuf>>,
+    next_ino: u64,
+}
+
+/// MemoryDir
+///
+/// An open directory of a MemoryFs
+pub struct MemoryDir {
+    path: PathBuf,
+    children: Vec<(PathBuf, u64, Kind)>,
+}
+
+impl Default for MemoryFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryFs {
+    pub fn new() -> MemoryFs {
+        MemoryFs {
+            info: FilesystemInfo::default(),
+            entries: BTreeMap::new(),
+            children: BTreeMap::new(),
+            next_ino: 1,
+        }
+    }
+
+    /// dir
+    ///
+    /// Add a directory
+    pub fn dir(&mut self, path: &str) -> &mut MemoryFs {
+        self.add(Path::new(path), Kind::Directory, 0);
+        self
+    }
+
+    /// file
+    ///
+    /// Add a regular file of size bytes
+    pub fn file(&mut self, path: &str, size: u64) -> &mut MemoryFs {
+        self.add(Path::new(path), Kind::File, size);
+        self
+    }
+
+    /// symlink
+    ///
+    /// Add a symlink, which traversal skips
+    #[allow(dead_code)]
+    pub fn symlink(&mut self, path: &str) -> &mut MemoryFs {
+        self.add(Path::new(path), Kind::Symlink, 0);
+        self
+    }
+
+    /// hard_link
+    ///
+    /// Add a second name for an existing file
+    #[allow(dead_code)]
+    pub fn hard_link(&mut self, existing: &str, path: &str) -> &mut MemoryFs {
+        if let Some(meta) = self.entries.get_mut(Path::new(existing)) {
+            meta.nlink += 1;
+            let meta = meta.clone();
+            for other in self.entries.values_mut() {
+                if other.ino == meta.ino {
+                    other.nlink = meta.nlink;
+                }
+            }
+            self.parents(Path::new(path), meta.dev);
+            self.entries.insert(PathBuf::from(path), meta);
+        }
+        self
+    }
+
+    /// born
+    ///
+    /// Set the birth and modification time of an entry in seconds since the
+    /// epoch
+    pub fn born(&mut self, path: &str, time: i64) -> &mut MemoryFs {
+        if let Some(meta) = self.entries.get_mut(Path::new(path)) {
+            meta.btime = Some(time);
+            meta.mtime = time;
+        }
+        self
+    }
+
+    /// with_device
+    ///
+    /// Move path and everything below it to another device, as if a
+    /// filesystem were mounted there
+    #[allow(dead_code)]
+    pub fn with_device(&mut self, path: &str, dev: u64) -> &mut MemoryFs {
+        for (entry, meta) in self.entries.iter_mut() {
+            if entry.starts_with(path) {
+                meta.dev = dev;
+            }
+        }
+        self
+    }
+
+    fn add(&mut self, path: &Path, kind: Kind, size: u64) {
+        if self.entries.contains_key(path) {
+            return;
+        }
+        let dev = self.parents(path, 1);
+        let meta = Meta {
+            kind,
+            dev,
+            ino: self.next_ino,
+            nlink: 1,
+            uid: 0,
+            size,
+            blocks: size.div_ceil(512),
+            mtime: 0,
+            btime: None,
+            mnt_id: None,
+        };
+        self.next_ino += 1;
+        self.entries.insert(path.to_path_buf(), meta);
+    }
+
+    /// parents
+    ///
+    /// Create the missing parents of path and list path as a child.  Returns
+    /// the device of the parent.
+    fn parents(&mut self, path: &Path, dev: u64) -> u64 {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                self.add(parent, Kind::Directory, 0);
+                self.children
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .insert(path.to_path_buf());
+                self.entries[parent].dev
+            }
+            _ => dev,
+        }
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<&Meta> {
+        self.entries
+            .get(path)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn open_path(&self, path: &Path) -> io::Result<MemoryDir> {
+        if self.lookup(path)?.kind != Kind::Directory {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            ));
+        }
+        let children = self
+            .children
+            .get(path)
+            .into_iter()
+            .flatten()
+            .rev()
+            .map(|child| {
+                (
+                    child.clone(),
+                    self.entries[child].ino,
+                    self.entries[child].kind,
+                )
+            })
+            .collect();
+        Ok(MemoryDir {
+            path: path.to_path_buf(),
+            children,
+        })
+    }
+}
+
+impl FsBackend for MemoryFs {
+    type Dir = MemoryDir;
+
+    fn open(&self, path: &Path) -> io::Result<MemoryDir> {
+        self.open_path(path)
+    }
+
+    fn open_at(&self, dir: &MemoryDir, entry: &DirEntry) -> io::Result<MemoryDir> {
+        self.open_path(&dir.path.join(entry.name()))
+    }
+
+    fn read_dir(&self, dir: &mut MemoryDir) -> Option<io::Result<DirEntry>> {
+        let (path, ino, kind) = dir.children.pop()?;
+        Some(DirEntry::new(path.file_name()?, ino, Some(kind)))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Meta> {
+        self.lookup(path).cloned()
+    }
+
+    fn dir_metadata(&self, dir: &MemoryDir) -> io::Result<Meta> {
+        self.lookup(&dir.path).cloned()
+    }
+
+    fn metadata(&self, dir: &MemoryDir, entries: &[DirEntry]) -> Vec<io::Result<Meta>> {
+        entries
+            .iter()
+            .map(|entry| self.lookup(&dir.path.join(entry.name())).cloned())
+            .collect()
+    }
+
+    fn device(&self, path: &Path) -> u64 {
+        self.lookup(path).map(|meta| meta.dev).unwrap_or(0)
+    }
+
+    fn filesystem(&self, _path: &Path) -> io::Result<FilesystemInfo> {
+        Ok(self.info.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "windows")]
+    #[test]
+    fn local_device() {
+        assert_eq!(Local.device(&PathBuf::from("/Users")), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn local_device() {
+        assert_ne!(Local.device(&PathBuf::from("/tmp")), 0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn local_device_error() {
+        assert_eq!(Local.device(&PathBuf::from("/doesnotexist")), 0);
+    }
+
+    #[test]
+    fn memory_read_dir() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/b", 2)
+            .file("root/a/c", 3)
+            .symlink("root/link");
+
+        let mut dir = fs.open(Path::new("root")).unwrap();
+        let mut names = vec![];
+        while let Some(entry) = fs.read_dir(&mut dir) {
+            names.push(entry.unwrap().name().to_string_lossy().to_string());
+        }
+        assert_eq!(names, vec!["a", "b", "link"]);
+        assert_eq!(fs.dir_metadata(&dir).unwrap().kind, Kind::Directory);
+        assert!(fs.open(Path::new("root/b")).is_err());
+        assert!(fs.open(Path::new("missing")).is_err());
+    }
+
+    #[test]
+    fn memory_metadata() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a", 5)
+            .hard_link("root/a", "root/b")
+            .with_device("root", 7);
+
+        let mut dir = fs.open(Path::new("root")).unwrap();
+        let entries: Vec<DirEntry> = std::iter::from_fn(|| fs.read_dir(&mut dir))
+            .map(|entry| entry.unwrap())
+            .collect();
+        let metas = fs.metadata(&dir, &entries);
+        let a = metas[0].as_ref().unwrap();
+        let b = metas[1].as_ref().unwrap();
+        assert_eq!(a.size, 5);
+        assert_eq!(a.ino, b.ino);
+        assert_eq!(a.nlink, 2);
+        assert_eq!(b.dev, 7);
+        assert_eq!(fs.device(Path::new("root")), 7);
+    }
+}
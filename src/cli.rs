@@ -1,5 +1,5 @@
 extern crate clap;
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 
 pub fn get_matches() -> ArgMatches<'static> {
     let app = App::new("DiskSpace")
@@ -7,35 +7,93 @@ pub fn get_matches() -> ArgMatches<'static> {
         .author("Eric Jackson <swiftgist@gmail.com>")
         .about("Displays disk space usage")
         .arg(
-            Arg::with_name("all")
-                .short("a")
-                .long("all")
-                .help("display all entries"),
+            Arg::with_name("exclude-fstype")
+                .long("exclude-fstype")
+                .value_name("TYPES")
+                .use_delimiter(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("skip mounted filesystems of comma separated TYPES"),
+        )
+        .arg(
+            Arg::with_name("include-fstype")
+                .long("include-fstype")
+                .value_name("TYPES")
+                .use_delimiter(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("only cross into mounted filesystems of comma separated TYPES"),
+        )
+        .arg(
+            Arg::with_name("time-limit")
+                .long("time-limit")
+                .value_name("DURATION")
+                .help("stop scanning after DURATION such as 30s, 500ms, 2m or 1h")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-entries")
+                .long("max-entries")
+                .value_name("N")
+                .help("stop scanning after visiting N entries")
+                .takes_value(true),
         )
         .arg(
-            Arg::with_name("color")
-                .short("c")
-                .long("color")
-                .value_name("COLOR")
-                .help("set to black, red, green, yellow, blue, magenta, cyan, white or none")
+            Arg::with_name("born-before")
+                .long("born-before")
+                .value_name("TIME")
+                .help("only count files created before TIME, a UTC date or @SECONDS")
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("lines")
-                .short("n")
-                .long("lines")
-                .value_name("LINES")
-                .help("display number of LINES of entries")
+            Arg::with_name("born-after")
+                .long("born-after")
+                .value_name("TIME")
+                .help("only count files created after TIME, a UTC date or @SECONDS")
                 .takes_value(true),
         )
         .arg(
-            Arg::with_name("exclude")
-                .short("e")
-                .long("exclude")
-                .min_values(1)
+            Arg::with_name("image")
+                .long("image")
+                .value_name("FILE")
                 .multiple(true)
-                .value_name("STRING")
-                .help("exclude lines containing STRING"),
+                .number_of_values(1)
+                .conflicts_with("directory")
+                .help("read an ext2, ext3 or ext4 filesystem image in place of directories"),
+        )
+        .arg(
+            Arg::with_name("files-from")
+                .long("files-from")
+                .value_name("FILE")
+                .conflicts_with_all(&["directory", "image"])
+                .help("count the paths listed in FILE, or stdin for -, without walking directories")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("null")
+                .long("null")
+                .short("0")
+                .requires("files-from")
+                .help("paths in --files-from end with NUL rather than newline"),
+        )
+        .arg(
+            Arg::with_name("export-ncdu")
+                .long("export-ncdu")
+                .value_name("FILE")
+                .help("write the scan to FILE, or stdout for -, in the JSON format of ncdu -o")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("s3-endpoint")
+                .long("s3-endpoint")
+                .value_name("URL")
+                .help("list s3:// directories from an S3-compatible store at URL")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("into-archives")
+                .long("into-archives")
+                .help("list the contents of tar and zip archives as directories"),
         )
         .arg(
             Arg::with_name("verbose")
@@ -43,11 +101,30 @@ pub fn get_matches() -> ArgMatches<'static> {
                 .long("verbose")
                 .help("display skipped entries"),
         )
-        .arg(
-            Arg::with_name("reverse")
-                .short("r")
-                .long("reverse")
-                .help("display entries descending"),
+        .args(&report_args())
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Reports du or ncdu output as ds would")
+                .args(&report_args())
+                .arg(
+                    Arg::with_name("import-du")
+                        .long("import-du")
+                        .value_name("FILE")
+                        .help("read the output of du -ab from FILE, or stdin for -")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("import-ncdu")
+                        .long("import-ncdu")
+                        .value_name("FILE")
+                        .help("read an ncdu JSON export from FILE, or stdin for -")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("import")
+                        .args(&["import-du", "import-ncdu"])
+                        .required(true),
+                ),
         )
         .arg(
             Arg::with_name("directory")
@@ -68,7 +145,135 @@ pub fn get_matches() -> ArgMatches<'static> {
     }
 }
 
+/// report_args
+///
+/// Options shaping the report, shared by a scan and ds report
+fn report_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("all")
+            .short("a")
+            .long("all")
+            .help("display all entries"),
+        Arg::with_name("color")
+            .short("c")
+            .long("color")
+            .value_name("WHEN")
+            .help("color sizes auto on a terminal, always or never")
+            .takes_value(true),
+        Arg::with_name("theme")
+            .long("theme")
+            .value_name("COLOR")
+            .help("color sizes black, red, green, yellow, blue, magenta, cyan or white")
+            .takes_value(true),
+        Arg::with_name("lines")
+            .short("n")
+            .long("lines")
+            .value_name("LINES")
+            .help("display number of LINES of entries")
+            .takes_value(true),
+        Arg::with_name("exclude")
+            .short("e")
+            .long("exclude")
+            .min_values(1)
+            .multiple(true)
+            .value_name("STRING")
+            .help("exclude lines containing STRING"),
+        Arg::with_name("files")
+            .long("files")
+            .conflicts_with_all(&["dirs", "leaves"])
+            .help("display only files"),
+        Arg::with_name("dirs")
+            .long("dirs")
+            .conflicts_with("leaves")
+            .help("display only directories"),
+        Arg::with_name("leaves")
+            .long("leaves")
+            .help("display only directories without subdirectories"),
+        Arg::with_name("collapse")
+            .long("collapse")
+            .value_name("PERCENT")
+            .min_values(0)
+            .max_values(1)
+            .require_equals(true)
+            .help("hide directories with a child holding more than PERCENT of them, 90 by default"),
+        Arg::with_name("sort")
+            .long("sort")
+            .value_name("KEYS")
+            .help("order entries by comma separated size, apparent, exclusive, count, mtime, name or depth")
+            .takes_value(true),
+        Arg::with_name("format")
+            .long("format")
+            .value_name("FORMAT")
+            .help("write entries as text, json, jsonl, csv, tsv or null with exact sizes")
+            .takes_value(true),
+        Arg::with_name("output-format")
+            .long("output-format")
+            .value_name("TEMPLATE")
+            .help("write each entry as TEMPLATE such as '{size:h} {count} {mtime:%F} {path}'")
+            .takes_value(true),
+        Arg::with_name("tree")
+            .long("tree")
+            .conflicts_with_all(&[
+                "output-format",
+                "sort",
+                "files",
+                "dirs",
+                "leaves",
+                "collapse",
+            ])
+            .help("draw entries under their parents with the percent of each parent"),
+        Arg::with_name("depth")
+            .long("depth")
+            .value_name("N")
+            .requires("tree")
+            .help("draw N levels below each root of the tree, 3 by default")
+            .takes_value(true),
+        Arg::with_name("children")
+            .long("children")
+            .value_name("N")
+            .requires("tree")
+            .help("draw the N largest children of each entry of the tree, 5 by default or all with -a")
+            .takes_value(true),
+        Arg::with_name("si")
+            .long("si")
+            .conflicts_with_all(&["block-size", "bytes"])
+            .help("display sizes in powers of 1000 rather than 1024"),
+        Arg::with_name("block-size")
+            .short("B")
+            .long("block-size")
+            .value_name("SIZE")
+            .conflicts_with("bytes")
+            .help("display sizes in blocks of SIZE such as K, M, 1M or 4KB, as du does")
+            .takes_value(true),
+        Arg::with_name("bytes")
+            .long("bytes")
+            .help("display sizes in exact bytes"),
+        Arg::with_name("precision")
+            .long("precision")
+            .value_name("N")
+            .help("display sizes in human units with N decimals")
+            .takes_value(true),
+        Arg::with_name("thousands")
+            .long("thousands")
+            .help("separate thousands of exact sizes with commas"),
+        Arg::with_name("no-truncate")
+            .long("no-truncate")
+            .help("display full paths rather than fit them to the terminal"),
+        Arg::with_name("percent")
+            .short("p")
+            .long("percent")
+            .help("display size as percentage of used filesystem space"),
+        Arg::with_name("reverse")
+            .short("r")
+            .long("reverse")
+            .help("display entries descending"),
+    ]
+}
+
 pub fn get_dirs(matches: &ArgMatches) -> Vec<String> {
+    if let Some(images) = matches.values_of("image") {
+        return images.map(|x| x.to_string()).collect();
+    }
     match matches.values_of("directory") {
         Some(start) => start.map(|x| x.to_string()).collect(),
         None => vec!["./".to_string()],
@@ -97,6 +302,24 @@ mod tests {
         assert_eq!(result, vec!["/tmp".to_string()]);
     }
 
+    #[test]
+    fn dirs_images() {
+        let args = vec!["ds", "--image", "a.img", "--image", "b.img"];
+        let matches = App::new("DiskSpace")
+            .arg(Arg::with_name("directory").min_values(0))
+            .arg(
+                Arg::with_name("image")
+                    .long("image")
+                    .multiple(true)
+                    .number_of_values(1),
+            )
+            .get_matches_from(args);
+
+        let result = get_dirs(&matches);
+
+        assert_eq!(result, vec!["a.img".to_string(), "b.img".to_string()]);
+    }
+
     #[test]
     fn dirs_default() {
         let args = vec!["ds"];
@@ -109,4 +332,18 @@ mod tests {
         assert_eq!(result, vec!["./".to_string()]);
     }
 
+    #[test]
+    fn tree_conflicts() {
+        for option in ["--sort=name", "--files", "--dirs", "--leaves", "--collapse"] {
+            let result = App::new("DiskSpace")
+                .args(&report_args())
+                .get_matches_from_safe(vec!["ds", "--tree", option]);
+            assert_eq!(
+                result.map(|_| ()).map_err(|err| err.kind),
+                Err(clap::ErrorKind::ArgumentConflict),
+                "{}",
+                option
+            );
+        }
+    }
 }
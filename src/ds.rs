@@ -1,24 +1,35 @@
+use crate::archive;
+use crate::backend::{FsBackend, Local};
+use crate::filesystem::FilesystemInfo;
+use crate::image::ExtImage;
+use crate::mounts::MountTable;
+use crate::progress::{Progress, CLEAR_LINE};
+use crate::report;
+use crate::s3::{is_s3_url, S3Client};
+use crate::signals;
+use crate::terminal::Stream;
+use crate::walk;
+use crate::walk::{Kind, Meta};
 use clap::ArgMatches;
 use std::collections::btree_map::Entry;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsString;
 use std::fmt;
-use std::fs;
+use std::fs::File;
 use std::io;
-#[cfg(target_os = "linux")]
-use std::os::linux::fs::MetadataExt;
-#[cfg(target_os = "windows")]
-use std::os::windows::fs::MetadataExt;
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::sync;
-// use std::time::Instant;
+use std::time::{Duration, Instant};
 
 /// Current implementation
 /// Expand upon the basic solution from ds4.rs.  Include proper error
 /// handling and replace unwrap() with ? where possible.  Increase
 /// functionality with additional command line options and windows
 /// support.
-
 pub enum DSError {
     IO(io::Error),
     Mutex,
@@ -26,7 +37,7 @@ pub enum DSError {
 
 impl fmt::Display for DSError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &*self {
+        match self {
             DSError::IO(err) => write!(f, "{}", err),
             DSError::Mutex => write!(f, "Mutex poisoned"),
         }
@@ -48,28 +59,39 @@ impl<T> From<sync::PoisonError<T>> for DSError {
 /// VerboseErrors
 ///
 /// Two flags to track whether files with errors should be printed or
-/// an informational message to the user.
+/// an informational message to the user.  Errors are counted for the
+/// progress line, which is cleared before a message is printed.
 pub struct VerboseErrors {
     pub verbose: bool,
+    pub progress: bool,
+    pub count: u64,
     once: bool,
 }
 
+impl Default for VerboseErrors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VerboseErrors {
     pub fn new() -> VerboseErrors {
         VerboseErrors {
             verbose: false,
+            progress: false,
+            count: 0,
             once: true,
         }
     }
 
-    pub fn display(&mut self, path: &PathBuf, err: io::Error) {
+    pub fn display(&mut self, path: &Path, err: io::Error) {
+        self.count += 1;
+        let clear = if self.progress { CLEAR_LINE } else { "" };
         if self.verbose {
-            eprintln!("{} {}", path.to_string_lossy().to_string(), err);
-        } else {
-            if self.once {
-                eprintln!("Use -v to see skipped files");
-                self.once = false;
-            }
+            eprintln!("{}{} {}", clear, path.to_string_lossy(), err);
+        } else if self.once {
+            eprintln!("{}Use -v to see skipped files", clear);
+            self.once = false;
         }
     }
 }
@@ -77,10 +99,19 @@ impl VerboseErrors {
 /// FilesystemDevice
 ///
 /// Linux supports filesystems independent of directory paths.  Support restricting
-/// calculations to a single filesystem.
+/// calculations to a single filesystem.  The device and mount id of the directory
+/// currently traversed are kept to notice when a mount point is crossed.  The
+/// mount id also tells apart bind mounts sharing a device.
 pub struct FilesystemDevice {
     pub enabled: bool,
     pub device: u64,
+    pub mount_id: Option<u64>,
+}
+
+impl Default for FilesystemDevice {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl FilesystemDevice {
@@ -88,39 +119,380 @@ impl FilesystemDevice {
         FilesystemDevice {
             enabled: false,
             device: 0,
+            mount_id: None,
+        }
+    }
+
+    /// crossed
+    ///
+    /// Check whether an entry is on another mount than the directory traversed.
+    /// Without statx only the device is compared.
+    pub fn crossed(&self, meta: &Meta) -> bool {
+        meta.dev != self.device || meta.mnt_id != self.mount_id
+    }
+
+    /// enter
+    ///
+    /// Make the mount of meta the one traversed.  Returns the previous one.
+    pub fn enter(&mut self, meta: &Meta) -> (u64, Option<u64>) {
+        let previous = (self.device, self.mount_id);
+        self.device = meta.dev;
+        self.mount_id = meta.mnt_id;
+        previous
+    }
+}
+
+/// Partial
+///
+/// Reason traversal stopped before visiting every entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Partial {
+    TimeLimit,
+    MaxEntries,
+    Interrupted,
+}
+
+impl fmt::Display for Partial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Partial::TimeLimit => write!(f, "time limit reached"),
+            Partial::MaxEntries => write!(f, "entry limit reached"),
+            Partial::Interrupted => write!(f, "interrupted"),
         }
     }
+}
+
+/// ScanBudget
+///
+/// Optional limits on scan time and number of entries visited.  Once either
+/// is exhausted or the scan is interrupted traversal stops and the results
+/// are partial.
+pub struct ScanBudget {
+    pub time_limit: Option<Duration>,
+    pub max_entries: Option<u64>,
+    pub entries: u64,
+    pub exhausted: Option<Partial>,
+    start: Instant,
+}
 
-    #[cfg(target_os = "windows")]
-    pub fn get(&mut self, _path: &PathBuf) -> u64 {
-        0
+impl Default for ScanBudget {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    #[cfg(not(target_os = "windows"))]
-    pub fn get(&mut self, path: &PathBuf) -> u64 {
-        if self.enabled {
-            match path.metadata() {
-                Ok(metadata) => metadata.st_dev(),
-                Err(_) => 0,
+impl ScanBudget {
+    pub fn new() -> ScanBudget {
+        ScanBudget {
+            time_limit: None,
+            max_entries: None,
+            entries: 0,
+            exhausted: None,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn settings(&mut self, matches: &ArgMatches) {
+        if let Some(time_limit) = matches.value_of("time-limit") {
+            match parse_duration(time_limit) {
+                Ok(duration) => self.time_limit = Some(duration),
+                Err(err) => eprintln!("Check time-limit option: {}", err),
             }
-        } else {
-            0
         }
+
+        if let Some(max_entries) = matches.value_of("max-entries") {
+            match max_entries.parse() {
+                Ok(max_entries) => self.max_entries = Some(max_entries),
+                Err(err) => eprintln!("Check max-entries option: {}", err),
+            }
+        }
+    }
+
+    /// start
+    ///
+    /// The time limit is measured from here.
+    pub fn start(&mut self) {
+        self.start = Instant::now();
+    }
+
+    /// visit
+    ///
+    /// Count an entry and check whether a limit has been reached.
+    pub fn visit(&mut self) {
+        self.entries += 1;
+        if signals::interrupted() {
+            self.exhausted = Some(Partial::Interrupted);
+        }
+        if let Some(max_entries) = self.max_entries {
+            if self.entries >= max_entries {
+                self.exhausted = Some(Partial::MaxEntries);
+            }
+        }
+        if let Some(time_limit) = self.time_limit {
+            if self.start.elapsed() >= time_limit {
+                self.exhausted = Some(Partial::TimeLimit);
+            }
+        }
+    }
+}
+
+/// parse_duration
+///
+/// Convert a number with an optional unit of ms, s, m or h into a Duration.
+/// A bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {}", value))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "ms" => number / 1000.0,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("invalid unit {} in {}", unit, value)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration {} is too long", value))
+}
+
+/// BornFilter
+///
+/// Optional bounds on the birth time of files.  Files outside the bounds, or
+/// without a recorded birth time while a bound is set, are not counted.
+pub struct BornFilter {
+    pub before: Option<i64>,
+    pub after: Option<i64>,
+}
+
+impl Default for BornFilter {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+impl BornFilter {
+    pub fn new() -> BornFilter {
+        BornFilter {
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn settings(&mut self, matches: &ArgMatches) {
+        if let Some(before) = matches.value_of("born-before") {
+            match parse_time(before) {
+                Ok(time) => self.before = Some(time),
+                Err(err) => eprintln!("Check born-before option: {}", err),
+            }
+        }
+
+        if let Some(after) = matches.value_of("born-after") {
+            match parse_time(after) {
+                Ok(time) => self.after = Some(time),
+                Err(err) => eprintln!("Check born-after option: {}", err),
+            }
+        }
+    }
+
+    /// admits
+    ///
+    /// Check whether a file is counted
+    pub fn admits(&self, meta: &Meta) -> bool {
+        if self.before.is_none() && self.after.is_none() {
+            return true;
+        }
+        match meta.btime {
+            Some(btime) => {
+                self.before.is_none_or(|before| btime < before)
+                    && self.after.is_none_or(|after| btime > after)
+            }
+            None => false,
+        }
+    }
+}
+
+/// parse_time
+///
+/// Convert a date of YYYY-MM-DD with an optional HH:MM[:SS] in UTC, or @SECONDS
+/// since the epoch, into seconds since the epoch.
+pub fn parse_time(value: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid time {}", value);
+    if let Some(seconds) = value.strip_prefix('@') {
+        return seconds.parse().map_err(|_| invalid());
+    }
+
+    let (date, time) = match value.find(['T', ' ']) {
+        Some(split) => (&value[..split], &value[split + 1..]),
+        None => (value, ""),
+    };
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|x| x.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<i64> = if time.is_empty() {
+        vec![]
+    } else {
+        time.split(':')
+            .map(|x| x.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?
+    };
+    if date.len() != 3 || time.len() == 1 || time.len() > 3 {
+        return Err(invalid());
+    }
+    let (year, month, day) = (date[0], date[1], date[2]);
+    let clock = [
+        time.first().copied().unwrap_or(0),
+        time.get(1).copied().unwrap_or(0),
+        time.get(2).copied().unwrap_or(0),
+    ];
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || clock[0] > 23
+        || clock[1] > 59
+        || clock[2] > 60
+        || clock.iter().any(|x| *x < 0)
+    {
+        return Err(invalid());
+    }
+
+    days_from_civil(year, month, day)
+        .and_then(|days| days.checked_mul(86400))
+        .and_then(|seconds| seconds.checked_add(clock[0] * 3600 + clock[1] * 60 + clock[2]))
+        .ok_or_else(invalid)
+}
+
+/// days_in_month
+///
+/// Length of a month of the proleptic Gregorian calendar
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// days_from_civil
+///
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar, None
+/// for years too far away to count
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146097)?.checked_add(day_of_era - 719468)
+}
+
+/// civil_from_days
+///
+/// Year, month and day of a count of days since 1970-01-01
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// listed_path
+///
+/// A path read from --files-from, without trailing slashes so it matches
+/// the parents of other listed paths.  Paths are bytes on Unix, so names
+/// need not be UTF-8.
+#[cfg(not(target_os = "windows"))]
+fn listed_path(line: Vec<u8>) -> PathBuf {
+    Path::new(&OsString::from_vec(line)).components().collect()
+}
+
+#[cfg(target_os = "windows")]
+fn listed_path(line: Vec<u8>) -> PathBuf {
+    let line = String::from_utf8_lossy(&line);
+    Path::new(line.trim_end_matches('\r'))
+        .components()
+        .collect()
+}
+
+/// estimate
+///
+/// Sum the used inodes of the filesystems holding the anchors, counting each
+/// filesystem once.  Used to estimate the percent complete of a scan.
+fn estimate<B: FsBackend>(anchors: &[String], backend: &B) -> u64 {
+    let mut devices = BTreeSet::new();
+    let mut inodes = 0;
+    for dir in anchors {
+        let path = Path::new(dir);
+        if devices.insert(backend.device(path)) {
+            if let Ok(info) = backend.filesystem(path) {
+                inodes += info.inodes_used;
+            }
+        }
+    }
+    inodes
+}
+
 /// DSGroup
 ///
 /// Data structures for calculations:
-///   inodes: file inodes
+///   inodes: device and inode of hard linked files
 ///   dirs: map of directory paths and list of children
 ///   sizes: final collection of sizes for all files and directories
+///   filesystems: capacity of the filesystem of each anchor and crossed mount
+///   incomplete: directories with entries left unvisited when the budget ran out
+///   anchors: starting directories
+///   archives: list members of archives with --into-archives
+///   images: anchors are ext2/3/4 images read with --image
+///   details: keep the metadata of each entry for --export-ncdu and --format
+///   metas: metadata of each directory and counted file with details
+///   counts: files counted in each directory with details, then the entries
+///           of each directory including itself, as du --inodes
+///   apparent: apparent size of each directory with details, counting the
+///             directories themselves as du --apparent-size does
+///   allocated: disk usage of each directory with details, as du
+///   exclusive: size of the files directly in each directory with details,
+///              leaving out subdirectories as du -S does
+///   unreadable: directories that could not be opened or read to the end,
+///               with details
 pub struct DSGroup {
     pub ve: VerboseErrors,
     pub fd: FilesystemDevice,
-    pub inodes: BTreeMap<u64, bool>,
+    pub mounts: MountTable,
+    pub budget: ScanBudget,
+    pub born: BornFilter,
+    pub progress: Progress,
+    pub inodes: BTreeMap<(u64, u64), bool>,
     pub dirs: BTreeMap<PathBuf, Vec<PathBuf>>,
     pub sizes: BTreeMap<String, u64>,
+    pub filesystems: BTreeMap<String, FilesystemInfo>,
+    pub incomplete: Vec<PathBuf>,
+    pub anchors: Vec<PathBuf>,
+    pub archives: bool,
+    pub images: bool,
+    pub details: bool,
+    pub metas: BTreeMap<PathBuf, Meta>,
+    pub counts: BTreeMap<String, u64>,
+    pub apparent: BTreeMap<String, u64>,
+    pub allocated: BTreeMap<String, u64>,
+    pub exclusive: BTreeMap<String, u64>,
+    pub unreadable: BTreeSet<PathBuf>,
+}
+
+impl Default for DSGroup {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DSGroup {
@@ -128,170 +500,617 @@ impl DSGroup {
         DSGroup {
             ve: VerboseErrors::new(),
             fd: FilesystemDevice::new(),
+            mounts: MountTable::new(),
+            budget: ScanBudget::new(),
+            born: BornFilter::new(),
+            progress: Progress::new(),
             inodes: BTreeMap::new(),
             dirs: BTreeMap::new(),
             sizes: BTreeMap::new(),
+            filesystems: BTreeMap::new(),
+            incomplete: Vec::new(),
+            anchors: Vec::new(),
+            archives: false,
+            images: false,
+            details: false,
+            metas: BTreeMap::new(),
+            counts: BTreeMap::new(),
+            apparent: BTreeMap::new(),
+            allocated: BTreeMap::new(),
+            exclusive: BTreeMap::new(),
+            unreadable: BTreeSet::new(),
         }
     }
 
     /// calculate
     ///
-    /// Check command line options.  Calculate file and directory size.  Append to map.
+    /// Check command line options.  Calculate file sizes for each anchor, then
+    /// directory sizes.  Anchors not reached within the budget are incomplete.
+    /// Anchors are local directories, s3:// buckets, or ext2/3/4 images with
+    /// --image.
     pub fn calculate(
         &mut self,
         anchors: &Vec<String>,
         matches: &ArgMatches,
     ) -> BTreeMap<String, u64> {
-        self.ve.verbose = matches.occurrences_of("verbose") > 0;
-        self.fd.enabled = matches.occurrences_of("one-filesystem") > 0;
+        self.settings(anchors, matches);
+        if self.progress.enabled && !self.images {
+            self.progress.estimate = estimate(anchors, &Local);
+        }
 
-        let mut diskspace = BTreeMap::new();
+        let client = S3Client::from_env(matches.value_of("s3-endpoint"));
+        for dir in anchors {
+            if is_s3_url(dir) {
+                match client.list(dir) {
+                    Ok(bucket) => {
+                        self.mounts.add_source(Path::new(dir), "s3");
+                        self.progress.estimate += bucket.info.inodes_used;
+                        self.scan(&bucket, dir, matches);
+                    }
+                    Err(err) => self.ve.display(Path::new(dir), err),
+                }
+                continue;
+            }
+            if !self.images {
+                self.scan(&Local, dir, matches);
+                continue;
+            }
+            match ExtImage::open(Path::new(dir)) {
+                Ok(image) => {
+                    self.mounts.add_source(Path::new(dir), image.fstype);
+                    self.progress.estimate += image.info.inodes_used;
+                    self.scan(&image, dir, matches);
+                }
+                Err(err) => self.ve.display(Path::new(dir), err),
+            }
+        }
 
+        self.finish()
+    }
+
+    /// calculate_with
+    ///
+    /// Calculate as above with every anchor read from backend
+    // only called through the library, by tests
+    #[allow(dead_code)]
+    pub fn calculate_with<B: FsBackend>(
+        &mut self,
+        backend: &B,
+        anchors: &Vec<String>,
+        matches: &ArgMatches,
+    ) -> BTreeMap<String, u64> {
+        self.settings(anchors, matches);
+        if self.progress.enabled {
+            self.progress.estimate = estimate(anchors, backend);
+        }
         for dir in anchors {
-            // let start = Instant::now();
-            let _ = match self.traverse(PathBuf::from(dir)) {
-                Ok(list) => list,
+            self.scan(backend, dir, matches);
+        }
+        self.finish()
+    }
+
+    /// calculate_files
+    ///
+    /// Check command line options.  Record the paths listed one per line in
+    /// files_from, or separated by NUL with --null, without walking any
+    /// directory.  A listed directory is shown but only counts the files
+    /// listed below it.  Each file is rolled up into its parent directories,
+    /// ending at the first component of the path.  The list is read from
+    /// stdin when files_from is -.
+    pub fn calculate_files(
+        &mut self,
+        files_from: &str,
+        matches: &ArgMatches,
+    ) -> BTreeMap<String, u64> {
+        let list: Box<dyn BufRead> = if files_from == "-" {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            match File::open(files_from) {
+                Ok(file) => Box::new(BufReader::new(file)),
                 Err(err) => {
-                    eprintln!("Error: {}", err);
+                    eprintln!("Error: {} {}", files_from, err);
                     process::exit(1);
                 }
+            }
+        };
+        let separator = if matches.occurrences_of("null") > 0 {
+            b'\0'
+        } else {
+            b'\n'
+        };
+        self.settings(&[], matches);
+
+        let mut tree: BTreeMap<PathBuf, BTreeSet<PathBuf>> = BTreeMap::new();
+        for line in list.split(separator) {
+            let line = match line {
+                Ok(line) => line,
+                Err(err) => {
+                    self.ve.display(Path::new(files_from), err);
+                    break;
+                }
             };
-            // let duration = start.elapsed();
-            // if duration.as_millis() > 100 {
-            //     println!("Time elapsed for files is: {:?}", duration);
-            // }
+            if line.is_empty() {
+                continue;
+            }
+            let path = listed_path(line);
+            if self.budget.exhausted.is_some() {
+                self.incomplete.push(path);
+                break;
+            }
+            self.budget.visit();
+            self.progress.visit(&path, self.ve.count);
 
-            // let start = Instant::now();
-            self.calculate_dirsize();
-            // let duration = start.elapsed();
-            // if duration.as_millis() > 100 {
-            //     println!("Time elapsed for dirs is: {:?}", duration);
-            // }
+            let meta = match walk::lstat(&path) {
+                Ok(meta) => meta,
+                Err(err) => {
+                    self.ve.display(&path, err);
+                    continue;
+                }
+            };
+            match meta.kind {
+                Kind::Symlink => continue,
+                Kind::Directory => {
+                    tree.entry(path.clone()).or_default();
+                    if self.details {
+                        self.metas.insert(path.clone(), meta);
+                    }
+                }
+                _ => self.record_filesize(&path, &meta),
+            }
 
-            diskspace.append(&mut self.sizes);
+            let mut child = path;
+            while let Some(parent) = child.parent() {
+                if parent.as_os_str().is_empty() {
+                    break;
+                }
+                let parent = parent.to_path_buf();
+                let known = tree.contains_key(&parent);
+                tree.entry(parent.clone()).or_default().insert(child);
+                if known {
+                    break;
+                }
+                child = parent;
+            }
         }
-        diskspace
+
+        let mut devices = BTreeSet::new();
+        let roots: Vec<PathBuf> = tree
+            .keys()
+            .filter(|dir| dir.parent().is_none_or(|parent| !tree.contains_key(parent)))
+            .cloned()
+            .collect();
+        self.anchors = roots.clone();
+        for root in roots {
+            let root = if root.is_relative() {
+                PathBuf::from(".")
+            } else {
+                root
+            };
+            if devices.insert(Local.device(&root)) {
+                self.record_filesystem(&Local, &root);
+            }
+        }
+        for (dir, children) in tree {
+            self.dirs.insert(dir, children.into_iter().collect());
+        }
+        self.finish()
+    }
+
+    /// settings
+    ///
+    /// Apply the command line options and start the budget
+    fn settings(&mut self, anchors: &[String], matches: &ArgMatches) {
+        self.ve.verbose = matches.occurrences_of("verbose") > 0;
+        self.fd.enabled = matches.occurrences_of("one-filesystem") > 0;
+        self.archives = matches.occurrences_of("into-archives") > 0;
+        self.images = matches.occurrences_of("image") > 0;
+        self.details = matches.occurrences_of("export-ncdu") > 0
+            || matches.occurrences_of("format") > 0
+            || matches.occurrences_of("output-format") > 0
+            || matches.occurrences_of("sort") > 0;
+        if let Some(include) = matches.values_of("include-fstype") {
+            self.mounts.include = include.map(|x| x.to_string()).collect();
+        }
+        if let Some(exclude) = matches.values_of("exclude-fstype") {
+            self.mounts.exclude = exclude.map(|x| x.to_string()).collect();
+        }
+        self.mounts.load();
+        self.budget.settings(matches);
+        self.born.settings(matches);
+        self.budget.start();
+        self.anchors = anchors.iter().map(PathBuf::from).collect();
+        self.ve.progress = self.progress.enabled;
+    }
+
+    /// scan
+    ///
+    /// Traverse one anchor unless the budget is exhausted or the filesystem
+    /// holding it is skipped, as its mount points below would be
+    fn scan<B: FsBackend>(&mut self, backend: &B, dir: &str, matches: &ArgMatches) {
+        if self.budget.exhausted.is_some() {
+            self.incomplete.push(PathBuf::from(dir));
+            return;
+        }
+        self.fd.device = 0;
+        self.fd.mount_id = None;
+        self.mounts.anchor(Path::new(dir));
+        if let Some(fstype) = self.mounts.filesystem_type(Path::new(dir)) {
+            if self.mounts.skip_anchor(fstype) {
+                eprintln!("Skipping {}: {} filesystem", dir, fstype);
+                return;
+            }
+        }
+        // let start = Instant::now();
+        if let Err(err) = self.traverse(backend, PathBuf::from(dir), matches) {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        }
+        // let duration = start.elapsed();
+        // if duration.as_millis() > 100 {
+        //     println!("Time elapsed for files is: {:?}", duration);
+        // }
+    }
+
+    /// finish
+    ///
+    /// Calculate directory sizes once every anchor is traversed
+    fn finish(&mut self) -> BTreeMap<String, u64> {
+        self.progress.finish();
+
+        // let start = Instant::now();
+        self.calculate_dirsize();
+        // let duration = start.elapsed();
+        // if duration.as_millis() > 100 {
+        //     println!("Time elapsed for dirs is: {:?}", duration);
+        // }
+
+        self.sizes.clone()
     }
 
     /// traverse
     ///
-    /// Recursively evaluate files and collect children for directories. Skip symlinks.
-    fn traverse(&mut self, path: PathBuf) -> Result<(), DSError> {
-        if path.is_dir() {
-            let contents = match fs::read_dir(&path) {
-                Ok(contents) => contents,
-                Err(_) => {
-                    return Ok(());
+    /// Open an anchor and walk it.  An anchor that is not a directory is recorded
+    /// as a file, and its members too when it is an archive.
+    fn traverse<B: FsBackend>(
+        &mut self,
+        backend: &B,
+        path: PathBuf,
+        matches: &ArgMatches,
+    ) -> Result<(), DSError> {
+        match backend.open(&path) {
+            Ok(dir) => match backend.dir_metadata(&dir) {
+                Ok(meta) => self.walk(backend, dir, path, &meta, matches),
+                Err(err) => self.ve.display(&path, err),
+            },
+            Err(err) => match backend.stat(&path) {
+                Ok(ref meta) if meta.kind != Kind::Directory => {
+                    self.record_filesize(&path, meta);
+                    if self.archives && meta.kind == Kind::File && self.born.admits(meta) {
+                        self.record_archive(&path, || backend.open_anchor_file(&path));
+                    }
                 }
-            };
+                _ => self.ve.display(&path, err),
+            },
+        }
+
+        Ok(())
+    }
 
-            let anchor_device = self.fd.get(&path);
-            let mut children = vec![];
+    /// walk
+    ///
+    /// Recursively evaluate the entries of an open directory and collect its
+    /// children.  Each entry costs at most one stat relative to the directory,
+    /// requested in batches of walk::BATCH entries.  Skip symlinks, other
+    /// mounts with -x and mount points of skipped filesystem types.  Print an
+    /// intermediate report when requested with SIGUSR1.
+    fn walk<B: FsBackend>(
+        &mut self,
+        backend: &B,
+        mut dir: B::Dir,
+        path: PathBuf,
+        meta: &Meta,
+        matches: &ArgMatches,
+    ) {
+        if self.fd.crossed(meta) {
+            self.record_filesystem(backend, &path);
+        }
+        let (parent_device, parent_mount_id) = self.fd.enter(meta);
+        if self.details {
+            self.metas.insert(path.clone(), meta.clone());
+        }
 
-            for entry in contents {
-                let child_path = entry.unwrap().path();
+        let mut children = vec![];
+        let mut entries = Vec::with_capacity(walk::BATCH);
+        let mut paths = Vec::with_capacity(walk::BATCH);
+        let mut done = false;
 
-                if anchor_device != self.fd.get(&child_path) {
+        while !done {
+            while entries.len() < walk::BATCH {
+                let entry = match backend.read_dir(&mut dir) {
+                    Some(entry) => entry,
+                    None => {
+                        done = true;
+                        break;
+                    }
+                };
+                if self.budget.exhausted.is_some() {
+                    self.incomplete.push(path.clone());
+                    done = true;
+                    break;
+                }
+
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        self.ve.display(&path, err);
+                        if self.details {
+                            self.unreadable.insert(path.clone());
+                        }
+                        done = true;
+                        break;
+                    }
+                };
+                if entry.kind == Some(Kind::Symlink) {
                     continue;
                 }
 
-                if self.is_symlink(&child_path) {
+                let child_path = path.join(entry.name());
+
+                if let Some(mount) = self.mounts.mount_point(&child_path) {
+                    if self.mounts.skip(&mount.fstype) {
+                        continue;
+                    }
+                }
+
+                entries.push(entry);
+                paths.push(child_path);
+            }
+
+            let metas = backend.metadata(&dir, &entries);
+            for ((entry, child_path), meta) in entries.drain(..).zip(paths.drain(..)).zip(metas) {
+                // a directory walked earlier in the batch may have used the budget
+                if self.budget.exhausted.is_some() {
+                    self.incomplete.push(path.clone());
+                    done = true;
+                    break;
+                }
+                self.budget.visit();
+                self.progress.visit(&path, self.ve.count);
+                if signals::report_requested() {
+                    self.intermediate_report(matches);
+                }
+
+                let meta = match meta {
+                    Ok(meta) => meta,
+                    Err(err) => {
+                        self.ve.display(&child_path, err);
+                        continue;
+                    }
+                };
+                if meta.kind == Kind::Symlink {
+                    continue;
+                }
+                if self.fd.enabled && self.fd.crossed(&meta) {
                     continue;
                 }
 
                 children.push(child_path.clone());
 
-                let _ = match self.traverse(child_path) {
-                    Ok(child) => child,
-                    Err(_) => return Ok(()),
-                };
+                if meta.kind == Kind::Directory {
+                    match backend.open_at(&dir, &entry) {
+                        Ok(child) => self.walk(backend, child, child_path, &meta, matches),
+                        Err(err) => {
+                            self.ve.display(&child_path, err);
+                            if self.details {
+                                self.metas.insert(child_path.clone(), meta);
+                                self.unreadable.insert(child_path);
+                            }
+                        }
+                    }
+                } else {
+                    self.record_filesize(&child_path, &meta);
+                    if self.archives && meta.kind == Kind::File && self.born.admits(&meta) {
+                        self.record_archive(&child_path, || backend.open_file(&dir, &entry));
+                    }
+                }
             }
-            self.dirs.insert(path, children);
-        } else {
-            self.record_filesize(&path);
         }
+        self.fd.device = parent_device;
+        self.fd.mount_id = parent_mount_id;
+        self.dirs.insert(path, children);
+    }
 
-        Ok(())
+    /// intermediate_report
+    ///
+    /// Print the top entries scanned so far to stderr without stopping.
+    fn intermediate_report(&mut self, matches: &ArgMatches) {
+        self.progress.finish();
+        let snapshot = self.snapshot();
+        report::report_stream(&mut io::stderr(), Stream::Stderr, snapshot, self, matches);
     }
 
-    /// record_filesize
+    /// snapshot
     ///
-    /// Retrieve the inode.  Retrieve the filesize if the inode is absent. Add entry
-    /// to sizes.  Skips hard links.
-    fn record_filesize(&mut self, path: &PathBuf) {
-        let inode = match path.metadata() {
-            #[cfg(target_os = "linux")]
-            Ok(metadata) => metadata.st_ino(),
-            #[cfg(target_os = "windows")]
-            Ok(metadata) => metadata.st_ino(),
-            Err(err) => {
-                self.ve.display(&path, err);
-                0
+    /// Directory sizes from the files recorded so far while traversal is still
+    /// running.  Each file size is added to its ancestors up to its anchor.
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        let mut snapshot = self.sizes.clone();
+        for (file, size) in &self.sizes {
+            for ancestor in Path::new(file).ancestors().skip(1) {
+                if ancestor.as_os_str().is_empty() {
+                    break;
+                }
+                if archive::is_archive_root(ancestor) {
+                    *snapshot
+                        .entry(ancestor.to_string_lossy().to_string())
+                        .or_insert(0) += size;
+                    break;
+                }
+                match self.anchors.iter().find(|anchor| *anchor == ancestor) {
+                    Some(anchor) => {
+                        *snapshot
+                            .entry(anchor.to_string_lossy().to_string())
+                            .or_insert(0) += size;
+                        break;
+                    }
+                    None => {
+                        *snapshot
+                            .entry(ancestor.to_string_lossy().to_string())
+                            .or_insert(0) += size;
+                    }
+                }
             }
-        };
+        }
+        snapshot
+    }
 
-        let filesize = match self.inodes.entry(inode) {
-            Entry::Vacant(_o) => {
-                self.inodes.insert(inode, false);
+    /// record_filesystem
+    ///
+    /// Retrieve the capacity of the filesystem holding path.  Called for each
+    /// anchor and each mount point crossed.
+    fn record_filesystem<B: FsBackend>(&mut self, backend: &B, path: &Path) {
+        match backend.filesystem(path) {
+            Ok(info) => {
+                self.filesystems
+                    .insert(path.to_string_lossy().to_string(), info);
+            }
+            Err(err) => self.ve.display(path, err),
+        }
+    }
 
-                match path.metadata() {
-                    #[cfg(target_os = "linux")]
-                    Ok(metadata) => metadata.st_size(),
-                    #[cfg(target_os = "windows")]
-                    Ok(metadata) => metadata.file_size(),
-                    Err(err) => {
-                        self.ve.display(&path, err);
-                        0
-                    }
+    /// record_filesize
+    ///
+    /// Add the file size to sizes.  Hard links are counted once by device and
+    /// inode.  Files outside the birth time bounds are not counted.
+    fn record_filesize(&mut self, path: &Path, meta: &Meta) {
+        if !self.born.admits(meta) {
+            return;
+        }
+        if self.details {
+            self.metas.insert(path.to_path_buf(), meta.clone());
+        }
+        if meta.nlink > 1 {
+            match self.inodes.entry((meta.dev, meta.ino)) {
+                Entry::Vacant(o) => {
+                    o.insert(false);
                 }
+                Entry::Occupied(_o) => return,
             }
-            Entry::Occupied(_o) => 0,
-        };
+        }
 
-        if filesize > 0 {
+        self.progress.bytes += meta.size;
+        if meta.size > 0 {
             self.sizes
-                .insert(path.to_string_lossy().to_string(), filesize);
+                .insert(path.to_string_lossy().to_string(), meta.size);
+        }
+        self.count_file(path);
+    }
+
+    /// count_file
+    ///
+    /// Count a file in its directory, only with details
+    fn count_file(&mut self, path: &Path) {
+        if !self.details {
+            return;
+        }
+        if let Some(parent) = path.parent() {
+            *self
+                .counts
+                .entry(parent.to_string_lossy().to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// record_archive
+    ///
+    /// Record the members of an archive below path! as if they were files and
+    /// directories.  The archive itself keeps its size on disk in its parent,
+    /// while the archive root holds the size of the contents.  The archive is
+    /// only opened when its name is that of an archive.
+    fn record_archive(&mut self, path: &Path, open: impl FnOnce() -> io::Result<File>) {
+        let kind = match path.file_name().and_then(archive::archive_kind) {
+            Some(kind) => kind,
+            None => return,
+        };
+        let members = match open().and_then(|file| archive::members(file, kind)) {
+            Ok(members) => members,
+            Err(err) => {
+                self.ve.display(path, err);
+                return;
+            }
+        };
+
+        let (dirs, sizes) = archive::tree(&archive::archive_root(path), &members);
+        for (dir, children) in dirs {
+            self.dirs.insert(dir, children.into_iter().collect());
+        }
+        for (file, size) in sizes {
+            self.count_file(&file);
+            self.sizes.insert(file.to_string_lossy().to_string(), size);
         }
     }
 
     /// calculate_dirsize
     ///
     /// Reverse the keys of the map and sum the children.  Hard links and symlinks
-    /// are omitted.
+    /// are omitted.  Counts, apparent sizes and disk usage add up the same way
+    /// with details, each directory counting itself, and the files directly
+    /// in each directory give its exclusive size.
     fn calculate_dirsize(&mut self) {
         for dir in self.dirs.keys().rev() {
             let mut dirsize: u64 = 0;
+            let name = dir.to_string_lossy().to_string();
+            let mut count = self.counts.get(&name).copied().unwrap_or(0) + 1;
+            let (mut apparent, mut allocated) = match self.metas.get(dir) {
+                Some(meta) => (meta.size, meta.blocks * 512),
+                None => (0, 0),
+            };
+            let mut exclusive: u64 = 0;
             if let Some(children) = self.dirs.get(dir) {
                 for child in children {
-                    let size = match self.sizes.get(&child.to_string_lossy().to_string()) {
+                    let name = child.to_string_lossy().to_string();
+                    let size = match self.sizes.get(&name) {
                         Some(size) => *size,
                         None => 0,
                     };
                     dirsize += size;
+                    if !self.details {
+                        continue;
+                    }
+                    // only directories have counts by now
+                    count += self.counts.get(&name).copied().unwrap_or(0);
+                    if self.dirs.contains_key(child) {
+                        apparent += self.apparent.get(&name).copied().unwrap_or(0);
+                        allocated += self.allocated.get(&name).copied().unwrap_or(0);
+                    } else if self.sizes.contains_key(&name) {
+                        exclusive += size;
+                        apparent += size;
+                        allocated += self.metas.get(child).map_or(0, |meta| meta.blocks * 512);
+                    }
                 }
             }
-            self.sizes
-                .insert(dir.to_string_lossy().to_string(), dirsize);
+            self.sizes.insert(name.clone(), dirsize);
+            if self.details {
+                self.counts.insert(name.clone(), count);
+                self.apparent.insert(name.clone(), apparent);
+                self.allocated.insert(name.clone(), allocated);
+                self.exclusive.insert(name, exclusive);
+            }
         }
     }
 
-    /// is_symlink
+    /// filesystem
     ///
-    /// Check if a path is a symlink.  Returns true if path is a symlink
-    /// or if the metadata results in an error.
-    fn is_symlink(&mut self, path: &PathBuf) -> bool {
-        match fs::symlink_metadata(&path) {
-            Ok(metadata) => {
-                if metadata.file_type().is_symlink() {
-                    return true;
-                }
-            }
-            Err(err) => {
-                self.ve.display(path, err);
-                return true;
-            }
-        }
-        false
+    /// Returns the filesystem holding path, which is the recorded anchor or
+    /// mount point with the longest matching prefix.  A recorded . holds
+    /// every relative path.
+    pub fn filesystem(&self, path: &str) -> Option<&FilesystemInfo> {
+        let path = Path::new(path);
+        self.filesystems
+            .iter()
+            .filter(|(root, _)| path.starts_with(root) || (*root == "." && path.is_relative()))
+            .max_by_key(|(root, _)| Path::new(root).components().count())
+            .map(|(_, info)| info)
     }
 }
 
@@ -299,13 +1118,15 @@ impl DSGroup {
 #[allow(unused_must_use)]
 mod tests {
     use super::*;
-    use std::io::{Error, ErrorKind};
+    use crate::backend::MemoryFs;
+    use clap::{App, Arg};
+    use std::io::Error;
 
     #[test]
     fn display() {
         let mut ve = VerboseErrors::new();
         ve.verbose = false;
-        let err = Error::new(ErrorKind::Other, "example");
+        let err = Error::other("example");
         assert_eq!(ve.display(&PathBuf::from("/some/path"), err), ());
     }
 
@@ -313,7 +1134,7 @@ mod tests {
     fn display_verbose() {
         let mut ve = VerboseErrors::new();
         ve.verbose = true;
-        let err = Error::new(ErrorKind::Other, "example");
+        let err = Error::other("example");
         assert_eq!(ve.display(&PathBuf::from("/some/path"), err), ());
     }
 
@@ -326,17 +1147,179 @@ mod tests {
     //
     //        mds.lock()
     //            .unwrap()
-    //            .insert("/tmp/does_not_exist".to_string(), 0 as u64);
+    //            .insert("/tmp/does_not_exist".to_string(), 0_u64);
     //        let result = increment(anchor, &mds, path, &mut ve).ok();
     //        assert_eq!(result, Some(()));
     //        assert_eq!(mds.lock().unwrap().get("/tmp/does_not_exist").unwrap(), &0);
     //    }
 
     #[test]
-    fn symlink_err() {
-        let path = PathBuf::from("/tmp/does_not_exist");
+    fn display_counts() {
         let mut ve = VerboseErrors::new();
-        assert_eq!(is_symlink(&path, &mut ve), true);
+        ve.display(&PathBuf::from("/some/path"), Error::other("example"));
+        ve.display(&PathBuf::from("/some/path"), Error::other("example"));
+        assert_eq!(ve.count, 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn estimate_once_per_filesystem() {
+        let once = estimate(&["/tmp".to_string()], &Local);
+        let twice = estimate(&["/tmp".to_string(), "/tmp".to_string()], &Local);
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn budget_max_entries() {
+        let mut budget = ScanBudget::new();
+        budget.max_entries = Some(2);
+        budget.visit();
+        assert_eq!(budget.exhausted, None);
+        budget.visit();
+        assert_eq!(budget.exhausted, Some(Partial::MaxEntries));
+    }
+
+    #[test]
+    fn budget_time_limit() {
+        let mut budget = ScanBudget::new();
+        budget.time_limit = Some(Duration::from_secs(0));
+        budget.start();
+        budget.visit();
+        assert_eq!(budget.exhausted, Some(Partial::TimeLimit));
+    }
+
+    #[test]
+    fn budget_unlimited() {
+        let mut budget = ScanBudget::new();
+        for _ in 0..1000 {
+            budget.visit();
+        }
+        assert_eq!(budget.exhausted, None);
+        assert_eq!(budget.entries, 1000);
+    }
+
+    #[test]
+    fn duration_units() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5h"), Ok(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn duration_invalid() {
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("100000000000000000000").is_err());
+    }
+
+    fn meta(dev: u64, mnt_id: Option<u64>, btime: Option<i64>) -> Meta {
+        Meta {
+            kind: Kind::File,
+            dev,
+            ino: 1,
+            nlink: 1,
+            uid: 0,
+            size: 1,
+            blocks: 1,
+            mtime: 0,
+            btime,
+            mnt_id,
+        }
+    }
+
+    #[test]
+    fn crossed_bind_mount() {
+        let mut fd = FilesystemDevice::new();
+        assert!(fd.crossed(&meta(5, Some(28), None)));
+        fd.enter(&meta(5, Some(28), None));
+        assert!(!fd.crossed(&meta(5, Some(28), None)));
+        // a bind mount shares the device
+        assert!(fd.crossed(&meta(5, Some(40), None)));
+        assert!(fd.crossed(&meta(6, Some(28), None)));
+    }
+
+    #[test]
+    fn crossed_without_statx() {
+        let mut fd = FilesystemDevice::new();
+        assert_eq!(fd.enter(&meta(5, None, None)), (0, None));
+        assert!(!fd.crossed(&meta(5, None, None)));
+        assert!(fd.crossed(&meta(6, None, None)));
+    }
+
+    #[test]
+    fn born_bounds() {
+        let mut born = BornFilter::new();
+        assert!(born.admits(&meta(1, None, None)));
+        born.after = Some(100);
+        born.before = Some(200);
+        assert!(born.admits(&meta(1, None, Some(150))));
+        assert!(!born.admits(&meta(1, None, Some(100))));
+        assert!(!born.admits(&meta(1, None, Some(250))));
+        assert!(!born.admits(&meta(1, None, None)));
+    }
+
+    #[test]
+    fn time_formats() {
+        assert_eq!(parse_time("@1700000000"), Ok(1700000000));
+        assert_eq!(parse_time("1970-01-01"), Ok(0));
+        assert_eq!(parse_time("2000-03-01"), Ok(951868800));
+        assert_eq!(parse_time("2024-02-29T12:30"), Ok(1709209800));
+        assert_eq!(parse_time("2024-02-29 12:30:15"), Ok(1709209815));
+        assert_eq!(parse_time("1969-12-31"), Ok(-86400));
+        assert_eq!(parse_time("2000-02-29"), Ok(951782400));
+        assert_eq!(parse_time("2024-12-31"), Ok(1735603200));
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        for days in [-800000, -1, 0, 59, 11016, 19782, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), Some(days));
+        }
+    }
+
+    #[test]
+    fn time_invalid() {
+        assert!(parse_time("yesterday").is_err());
+        assert!(parse_time("2024-13-01").is_err());
+        assert!(parse_time("2024-01").is_err());
+        assert!(parse_time("2024-01-01T25:00").is_err());
+        assert!(parse_time("2024-01-01T12").is_err());
+        assert!(parse_time("@soon").is_err());
+        assert!(parse_time("2024-02-30").is_err());
+        assert!(parse_time("2023-02-29").is_err());
+        assert!(parse_time("1900-02-29").is_err());
+        assert!(parse_time("2024-04-31").is_err());
+        assert!(parse_time("99999999999999-01-01").is_err());
+        assert!(parse_time("9223372036854775807-12-31").is_err());
+    }
+
+    #[test]
+    fn fmt_partial() {
+        assert_eq!(format!("{}", Partial::TimeLimit), "time limit reached");
+        assert_eq!(format!("{}", Partial::MaxEntries), "entry limit reached");
+        assert_eq!(format!("{}", Partial::Interrupted), "interrupted");
+    }
+
+    #[test]
+    fn snapshot_rolls_up_to_anchor() {
+        let mut group = DSGroup::new();
+        group.anchors = vec![PathBuf::from("./")];
+        group.sizes.insert("./a/b/fileA".to_string(), 10);
+        group.sizes.insert("./a/fileB".to_string(), 5);
+
+        let snapshot = group.snapshot();
+        assert_eq!(snapshot["./a/b/fileA"], 10);
+        assert_eq!(snapshot["./a/b"], 10);
+        assert_eq!(snapshot["./a"], 15);
+        assert_eq!(snapshot["./"], 15);
+        assert_eq!(snapshot.len(), 5);
     }
 
     #[test]
@@ -348,7 +1331,7 @@ mod tests {
     #[test]
     fn cast_ioerror() {
         fn nothing() -> DSError {
-            let err = Error::new(ErrorKind::Other, "example");
+            let err = Error::other("example");
             From::from(err)
         }
 
@@ -367,38 +1350,108 @@ mod tests {
     //        assert_eq!(result, "Mutex poisoned");
     //    }
 
-    #[cfg(target_os = "linux")]
     #[test]
-    fn filesystem_device_disabled() {
-        let mut fd = FilesystemDevice::new();
-        let result = fd.get(&PathBuf::from("/tmp"));
-        assert_eq!(result, 0);
+    fn filesystem_longest_prefix() {
+        let mut group = DSGroup::new();
+        let root = FilesystemInfo {
+            used: 1,
+            ..Default::default()
+        };
+        let home = FilesystemInfo {
+            used: 2,
+            ..Default::default()
+        };
+        group.filesystems.insert("/".to_string(), root.clone());
+        group.filesystems.insert("/home".to_string(), home.clone());
+
+        assert_eq!(group.filesystem("/home/user/file"), Some(&home));
+        assert_eq!(group.filesystem("/homework"), Some(&root));
+        assert_eq!(group.filesystem("relative"), None);
+    }
+
+    #[test]
+    fn filesystem_relative() {
+        let mut group = DSGroup::new();
+        let cwd = FilesystemInfo {
+            used: 3,
+            ..Default::default()
+        };
+        group.filesystems.insert(".".to_string(), cwd.clone());
+
+        assert_eq!(group.filesystem("src/main.rs"), Some(&cwd));
+        assert_eq!(group.filesystem("./src"), Some(&cwd));
+        assert_eq!(group.filesystem("/src"), None);
     }
 
     #[cfg(target_os = "linux")]
     #[test]
-    fn filesystem_device_enabled() {
-        let mut fd = FilesystemDevice::new();
-        fd.enabled = true;
-        let result = fd.get(&PathBuf::from("/tmp"));
-        assert_ne!(result, 0);
+    fn listed_paths() {
+        assert_eq!(listed_path(b"src/".to_vec()), PathBuf::from("src"));
+        assert_eq!(listed_path(b"./a/./b".to_vec()), PathBuf::from("./a/b"));
+        assert_eq!(listed_path(b"bad\xff".to_vec()).as_os_str().len(), 4);
+    }
+
+    fn scan(fs: &MemoryFs, args: &[&str]) -> DSGroup {
+        let matches = App::new("ds")
+            .arg(Arg::with_name("one-filesystem").short("x"))
+            .arg(Arg::with_name("sort").long("sort").takes_value(true))
+            .get_matches_from(args);
+        let mut group = DSGroup::new();
+        group.calculate_with(fs, &vec!["root".to_string()], &matches);
+        group
     }
 
-    #[cfg(target_os = "windows")]
     #[test]
-    fn filesystem_device_enabled() {
-        let mut fd = FilesystemDevice::new();
-        fd.enabled = true;
-        let result = fd.get(&PathBuf::from("/Users"));
-        assert_eq!(result, 0);
+    fn memory_rollup() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a/b/c", 3)
+            .file("root/a/d", 4)
+            .file("root/e", 5)
+            .symlink("root/a/link");
+        let group = scan(&fs, &["ds"]);
+
+        assert_eq!(group.sizes["root"], 12);
+        assert_eq!(group.sizes["root/a"], 7);
+        assert_eq!(group.sizes["root/a/b"], 3);
+        assert!(!group.sizes.contains_key("root/a/link"));
     }
 
-    #[cfg(target_os = "linux")]
     #[test]
-    fn filesystem_device_error() {
-        let mut fd = FilesystemDevice::new();
-        fd.enabled = true;
-        let result = fd.get(&PathBuf::from("/doesnotexist"));
-        assert_eq!(result, 0);
+    fn memory_hard_links_once() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a/file", 10)
+            .hard_link("root/a/file", "root/b/file");
+        let group = scan(&fs, &["ds"]);
+
+        assert_eq!(group.sizes["root"], 10);
+    }
+
+    #[test]
+    fn memory_one_filesystem() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a", 1)
+            .file("root/mnt/b", 2)
+            .with_device("root/mnt", 2);
+
+        assert_eq!(scan(&fs, &["ds"]).sizes["root"], 3);
+        let group = scan(&fs, &["ds", "-x"]);
+        assert_eq!(group.sizes["root"], 1);
+        assert!(!group.sizes.contains_key("root/mnt/b"));
+    }
+
+    #[test]
+    fn memory_exclusive() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a/b/c", 3)
+            .file("root/a/d", 4)
+            .file("root/a/e", 5)
+            .file("root/f", 6);
+        let group = scan(&fs, &["ds", "--sort", "exclusive"]);
+
+        assert_eq!(group.exclusive["root"], 6);
+        assert_eq!(group.exclusive["root/a"], 9);
+        assert_eq!(group.exclusive["root/a/b"], 3);
+        assert_eq!(group.sizes["root/a"], 12);
+        assert!(scan(&fs, &["ds"]).exclusive.is_empty());
     }
 }
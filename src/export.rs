@@ -0,0 +1,192 @@
+use crate::ds::DSGroup;
+use crate::walk::{Kind, Meta};
+use serde_json::{json, Map, Value};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// export
+///
+/// Write the scan to the file of --export-ncdu, or stdout for -.  Exits when
+/// the file cannot be written.  An ncdu export holds a single directory, so
+/// several anchors are an error.
+pub fn export(file: &str, group: &DSGroup) {
+    let root = match group.anchors.as_slice() {
+        [root] => root,
+        _ => {
+            eprintln!("Check export-ncdu option: ncdu exports hold one directory");
+            process::exit(1);
+        }
+    };
+    let result = if file == "-" {
+        export_ncdu(&mut BufWriter::new(io::stdout().lock()), group, root)
+    } else {
+        File::create(file).and_then(|out| export_ncdu(&mut BufWriter::new(out), group, root))
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {} {}", file, err);
+        process::exit(1);
+    }
+}
+
+/// export_ncdu
+///
+/// Write the tree below root in the JSON export format of ncdu 1.x, as read
+/// by ncdu -f.  Each directory is an array of its own entry followed by its
+/// children.  Files left out of the sizes by a filter are marked excluded,
+/// and directories that could not be read are marked with read_error.
+pub fn export_ncdu(out: &mut dyn Write, group: &DSGroup, root: &Path) -> io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let header = json!({
+        "progname": "ds",
+        "progver": env!("CARGO_PKG_VERSION"),
+        "timestamp": timestamp,
+    });
+    write!(out, "[1,2,{},", header)?;
+    directory(out, group, root, &root.to_string_lossy(), None)?;
+    writeln!(out, "]")?;
+    out.flush()
+}
+
+fn directory(
+    out: &mut dyn Write,
+    group: &DSGroup,
+    path: &Path,
+    name: &str,
+    parent_dev: Option<u64>,
+) -> io::Result<()> {
+    let meta = group.metas.get(path);
+    let mut info = entry(name, meta, parent_dev);
+    if group.unreadable.contains(path) {
+        info["read_error"] = json!(true);
+    }
+    write!(out, "[{}", info)?;
+    let dev = meta.map(|meta| meta.dev).or(parent_dev);
+    for child in group.dirs.get(path).into_iter().flatten() {
+        let name = child
+            .file_name()
+            .map_or_else(|| child.to_string_lossy(), |name| name.to_string_lossy());
+        write!(out, ",")?;
+        if group.dirs.contains_key(child) || group.unreadable.contains(child) {
+            directory(out, group, child, &name, dev)?;
+        } else {
+            match group.metas.get(child) {
+                Some(meta) => write!(out, "{}", entry(&name, Some(meta), dev))?,
+                None => write!(out, "{}", json!({"name": name, "excluded": "pattern"}))?,
+            }
+        }
+    }
+    write!(out, "]")
+}
+
+/// entry
+///
+/// The info object of an entry.  The device is only written where it differs
+/// from the parent, and hard links are flagged for ncdu to count once.
+fn entry(name: &str, meta: Option<&Meta>, parent_dev: Option<u64>) -> Value {
+    let mut info = Map::new();
+    info.insert("name".to_string(), json!(name));
+    if let Some(meta) = meta {
+        info.insert("asize".to_string(), json!(meta.size));
+        info.insert("dsize".to_string(), json!(meta.blocks * 512));
+        if parent_dev != Some(meta.dev) {
+            info.insert("dev".to_string(), json!(meta.dev));
+        }
+        info.insert("ino".to_string(), json!(meta.ino));
+        if meta.kind != Kind::Directory && meta.nlink > 1 {
+            info.insert("hlnkc".to_string(), json!(true));
+            info.insert("nlink".to_string(), json!(meta.nlink));
+        }
+        if meta.kind == Kind::Other {
+            info.insert("notreg".to_string(), json!(true));
+        }
+    }
+    Value::Object(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryFs;
+    use crate::import::import_ncdu;
+    use clap::{App, Arg};
+    use std::path::PathBuf;
+
+    #[test]
+    fn round_trip() {
+        let mut fs = MemoryFs::new();
+        fs.file("root/a/b", 30)
+            .file("root/c", 5)
+            .hard_link("root/c", "root/a/d")
+            .dir("root/empty");
+        let matches = App::new("ds")
+            .arg(
+                Arg::with_name("export-ncdu")
+                    .long("export-ncdu")
+                    .takes_value(true),
+            )
+            .get_matches_from(vec!["ds", "--export-ncdu", "-"]);
+        let mut group = DSGroup::new();
+        group.calculate_with(&fs, &vec!["root".to_string()], &matches);
+
+        let mut out = vec![];
+        export_ncdu(&mut out, &group, Path::new("root")).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("[1,2,{\"progname\":\"ds\""));
+        assert!(text.contains("\"hlnkc\":true"));
+
+        // the hard link is counted where it is first seen, as in the scan
+        let imported = import_ncdu(text.as_bytes()).unwrap();
+        assert_eq!(imported["root/a/b"], 30);
+        assert_eq!(imported["root/a"], 35);
+        assert!(!imported.contains_key("root/c"));
+        assert_eq!(imported["root/empty"], 0);
+        assert_eq!(imported["root"], group.sizes["root"]);
+    }
+
+    #[test]
+    fn read_error() {
+        let mut group = DSGroup::new();
+        group.dirs.insert(
+            PathBuf::from("root"),
+            vec![PathBuf::from("root/locked"), PathBuf::from("root/gone")],
+        );
+        group.unreadable.insert(PathBuf::from("root/locked"));
+
+        let mut out = vec![];
+        export_ncdu(&mut out, &group, Path::new("root")).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("[{\"name\":\"locked\",\"read_error\":true}]"));
+        assert!(text.contains("{\"excluded\":\"pattern\",\"name\":\"gone\"}"));
+        assert!(import_ncdu(text.as_bytes())
+            .unwrap()
+            .contains_key("root/locked"));
+    }
+
+    #[test]
+    fn entry_fields() {
+        let meta = Meta {
+            kind: Kind::File,
+            dev: 7,
+            ino: 3,
+            nlink: 1,
+            uid: 0,
+            size: 10,
+            blocks: 8,
+            mtime: 0,
+            btime: None,
+            mnt_id: None,
+        };
+        assert_eq!(
+            entry("f", Some(&meta), Some(7)),
+            json!({"name": "f", "asize": 10, "dsize": 4096, "ino": 3})
+        );
+        assert_eq!(entry("f", Some(&meta), Some(1))["dev"], json!(7));
+        assert_eq!(entry("d", None, None), json!({"name": "d"}));
+    }
+}
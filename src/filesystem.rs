@@ -0,0 +1,119 @@
+#[cfg(not(target_os = "windows"))]
+use std::ffi::CString;
+use std::io;
+#[cfg(not(target_os = "windows"))]
+use std::mem;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// FilesystemInfo
+///
+/// Capacity of the filesystem holding a path as reported by statvfs.  Free
+/// space is what an unprivileged user may allocate; reserved space is the
+/// remainder held back for root.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FilesystemInfo {
+    pub total: u64,
+    pub used: u64,
+    pub free: u64,
+    pub reserved: u64,
+    pub inodes: u64,
+    pub inodes_used: u64,
+}
+
+impl FilesystemInfo {
+    #[cfg(target_os = "windows")]
+    pub fn new(_path: &Path) -> io::Result<FilesystemInfo> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "statvfs is not supported",
+        ))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    pub fn new(path: &Path) -> io::Result<FilesystemInfo> {
+        let cpath = CString::new(path.as_os_str().as_bytes())?;
+        let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+        if unsafe { libc::statvfs(cpath.as_ptr(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fragment = stat.f_frsize as u64;
+        let blocks = stat.f_blocks as u64;
+        let bfree = stat.f_bfree as u64;
+        let bavail = stat.f_bavail as u64;
+        let files = stat.f_files as u64;
+        let ffree = stat.f_ffree as u64;
+
+        Ok(FilesystemInfo {
+            total: blocks * fragment,
+            used: blocks.saturating_sub(bfree) * fragment,
+            free: bavail * fragment,
+            reserved: bfree.saturating_sub(bavail) * fragment,
+            inodes: files,
+            inodes_used: files.saturating_sub(ffree),
+        })
+    }
+
+    /// percent
+    ///
+    /// Share of the used space of this filesystem taken by size.
+    pub fn percent(&self, size: u64) -> f64 {
+        if self.used == 0 {
+            0.0
+        } else {
+            size as f64 * 100.0 / self.used as f64
+        }
+    }
+
+    /// inodes_percent
+    ///
+    /// Share of inodes in use.
+    pub fn inodes_percent(&self) -> f64 {
+        if self.inodes == 0 {
+            0.0
+        } else {
+            self.inodes_used as f64 * 100.0 / self.inodes as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn statvfs_tmp() {
+        let info = FilesystemInfo::new(Path::new("/tmp")).unwrap();
+        assert!(info.total > 0);
+        assert!(info.used <= info.total);
+        assert!(info.free + info.reserved + info.used <= info.total);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn statvfs_error() {
+        assert!(FilesystemInfo::new(Path::new("/doesnotexist")).is_err());
+    }
+
+    #[test]
+    fn percent() {
+        let info = FilesystemInfo {
+            used: 2048,
+            inodes: 10,
+            inodes_used: 5,
+            ..Default::default()
+        };
+        assert_eq!(info.percent(512), 25.0);
+        assert_eq!(info.inodes_percent(), 50.0);
+    }
+
+    #[test]
+    fn percent_empty() {
+        let info = FilesystemInfo::default();
+        assert_eq!(info.percent(512), 0.0);
+        assert_eq!(info.inodes_percent(), 0.0);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::ds::DSGroup;
+use crate::walk::Kind;
+use serde_json::{json, Map, Value};
+use std::io;
+use std::io::Write;
+use std::path::Path;
+
+/// Format
+///
+/// Layout of the report chosen with --format.  Text is the aligned, colored
+/// listing; the others are for scripts and hold exact sizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Jsonl,
+    Csv,
+    Tsv,
+    Null,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Result<Format, String> {
+        match value {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "null" => Ok(Format::Null),
+            _ => Err(format!(
+                "{} is not one of text, json, jsonl, csv, tsv or null",
+                value
+            )),
+        }
+    }
+}
+
+/// Row
+///
+/// The fields of a reported entry.  Times are seconds since the epoch and
+/// only known for entries whose metadata was kept, as are the disk usage and
+/// owner.  The exclusive size of a directory leaves out its subdirectories,
+/// that of a file is its size.
+#[derive(Debug, Default, PartialEq)]
+pub struct Row<'a> {
+    pub path: &'a str,
+    pub size: u64,
+    pub kind: &'static str,
+    pub count: u64,
+    pub mtime: Option<i64>,
+    pub btime: Option<i64>,
+    pub apparent: u64,
+    pub allocated: Option<u64>,
+    pub exclusive: u64,
+    pub uid: Option<u32>,
+}
+
+impl<'a> Row<'a> {
+    /// new
+    ///
+    /// Look up the fields of path in the scan.  Directories count the entries
+    /// below them and themselves, files count one.
+    pub fn new(group: &DSGroup, path: &'a str, size: u64) -> Row<'a> {
+        let meta = group.metas.get(Path::new(path));
+        let directory = group.dirs.contains_key(Path::new(path));
+        let kind = match meta.map(|meta| meta.kind) {
+            Some(Kind::Directory) => "directory",
+            Some(Kind::File) => "file",
+            Some(_) => "other",
+            None if directory => "directory",
+            None => "file",
+        };
+        let count = if directory {
+            group.counts.get(path).copied().unwrap_or(1)
+        } else {
+            1
+        };
+        let (apparent, allocated, exclusive) = if directory {
+            (
+                group.apparent.get(path).copied().unwrap_or(size),
+                group.allocated.get(path).copied(),
+                group.exclusive.get(path).copied().unwrap_or(size),
+            )
+        } else {
+            (size, meta.map(|meta| meta.blocks * 512), size)
+        };
+        Row {
+            path,
+            size,
+            kind,
+            count,
+            mtime: meta.map(|meta| meta.mtime),
+            btime: meta.and_then(|meta| meta.btime),
+            apparent,
+            allocated,
+            exclusive,
+            uid: meta.map(|meta| meta.uid),
+        }
+    }
+
+    fn json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("path".to_string(), json!(self.path));
+        object.insert("size".to_string(), json!(self.size));
+        object.insert("type".to_string(), json!(self.kind));
+        object.insert("count".to_string(), json!(self.count));
+        if let Some(mtime) = self.mtime {
+            object.insert("mtime".to_string(), json!(mtime));
+        }
+        if let Some(btime) = self.btime {
+            object.insert("btime".to_string(), json!(btime));
+        }
+        Value::Object(object)
+    }
+
+    /// fields
+    ///
+    /// The columns of csv and tsv, unknown times left empty
+    fn fields(&self) -> [String; 5] {
+        let time = |time: Option<i64>| time.map_or_else(String::new, |time| time.to_string());
+        [
+            self.size.to_string(),
+            self.kind.to_string(),
+            self.count.to_string(),
+            time(self.mtime),
+            time(self.btime),
+        ]
+    }
+}
+
+/// Columns of csv and tsv
+const HEADER: [&str; 6] = ["size", "type", "count", "mtime", "btime", "path"];
+
+/// write_rows
+///
+/// Write the rows in a machine readable format.  json is a single array,
+/// jsonl an object per line, csv quotes fields as RFC 4180, tsv escapes
+/// tabs, newlines and backslashes, and null writes size, tab and path
+/// ending in NUL like du -0b.
+pub fn write_rows(out: &mut dyn Write, format: Format, rows: &[Row]) -> io::Result<()> {
+    match format {
+        Format::Text => {}
+        Format::Json => {
+            write!(out, "[")?;
+            for (index, row) in rows.iter().enumerate() {
+                let separator = if index == 0 { "" } else { "," };
+                write!(out, "{}\n{}", separator, row.json())?;
+            }
+            writeln!(out, "\n]")?;
+        }
+        Format::Jsonl => {
+            for row in rows {
+                writeln!(out, "{}", row.json())?;
+            }
+        }
+        Format::Csv => {
+            writeln!(out, "{}", HEADER.join(","))?;
+            for row in rows {
+                let [size, kind, count, mtime, btime] = row.fields();
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    size,
+                    kind,
+                    count,
+                    mtime,
+                    btime,
+                    csv_quote(row.path)
+                )?;
+            }
+        }
+        Format::Tsv => {
+            writeln!(out, "{}", HEADER.join("\t"))?;
+            for row in rows {
+                let mut fields = row.fields().to_vec();
+                fields.push(tsv_escape(row.path));
+                writeln!(out, "{}", fields.join("\t"))?;
+            }
+        }
+        Format::Null => {
+            for row in rows {
+                write!(out, "{}\t{}\0", row.size, row.path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn csv_quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn tsv_escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows() -> Vec<Row<'static>> {
+        vec![
+            Row {
+                path: "/tmp/a dir",
+                size: 2048,
+                kind: "directory",
+                count: 3,
+                mtime: Some(1700000000),
+                btime: None,
+                ..Row::default()
+            },
+            Row {
+                path: "/tmp/a dir/odd,\"name\"\n",
+                size: 2047,
+                kind: "file",
+                count: 1,
+                mtime: None,
+                btime: None,
+                ..Row::default()
+            },
+        ]
+    }
+
+    fn written(format: Format) -> String {
+        let mut out = vec![];
+        write_rows(&mut out, format, &rows()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(Format::parse("jsonl"), Ok(Format::Jsonl));
+        assert!(Format::parse("xml").is_err());
+    }
+
+    #[test]
+    fn json() {
+        let value: Value = serde_json::from_str(&written(Format::Json)).unwrap();
+        assert_eq!(value[0]["size"], 2048);
+        assert_eq!(value[0]["mtime"], 1700000000);
+        assert_eq!(value[1]["path"], "/tmp/a dir/odd,\"name\"\n");
+        assert!(value[1].get("mtime").is_none());
+        assert_eq!(written(Format::Jsonl).lines().count(), 2);
+        assert_eq!(write_rows(&mut vec![], Format::Json, &[]).ok(), Some(()));
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            written(Format::Csv),
+            "size,type,count,mtime,btime,path\n\
+             2048,directory,3,1700000000,,/tmp/a dir\n\
+             2047,file,1,,,\"/tmp/a dir/odd,\"\"name\"\"\n\"\n"
+        );
+    }
+
+    #[test]
+    fn tsv() {
+        assert_eq!(
+            written(Format::Tsv),
+            "size\ttype\tcount\tmtime\tbtime\tpath\n\
+             2048\tdirectory\t3\t1700000000\t\t/tmp/a dir\n\
+             2047\tfile\t1\t\t\t/tmp/a dir/odd,\"name\"\\n\n"
+        );
+    }
+
+    #[test]
+    fn null() {
+        assert_eq!(
+            written(Format::Null),
+            "2048\t/tmp/a dir\x002047\t/tmp/a dir/odd,\"name\"\n\x00"
+        );
+    }
+}
@@ -0,0 +1,673 @@
+use crate::backend::FsBackend;
+use crate::filesystem::FilesystemInfo;
+use crate::walk::{DirEntry, Kind, Meta};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::ffi::OsStringExt;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::fs::{FileExt, MetadataExt};
+#[cfg(target_os = "windows")]
+use std::os::windows::fs::FileExt;
+use std::path::Path;
+
+/// Inode of the root directory
+pub const ROOT_INODE: u32 = 2;
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const SUPERBLOCK_SIZE: usize = 1024;
+const MAGIC: u16 = 0xEF53;
+
+const COMPAT_HAS_JOURNAL: u32 = 0x4;
+const INCOMPAT_FILETYPE: u32 = 0x2;
+const INCOMPAT_EXTENTS: u32 = 0x40;
+const INCOMPAT_64BIT: u32 = 0x80;
+const INCOMPAT_FLEX_BG: u32 = 0x200;
+
+const HUGE_FILE_FL: u32 = 0x40000;
+const EXTENTS_FL: u32 = 0x80000;
+const INLINE_DATA_FL: u32 = 0x1000_0000;
+const EXTENT_MAGIC: u16 = 0xF30A;
+
+const S_IFMT: u16 = 0o170000;
+const S_IFREG: u16 = 0o100000;
+const S_IFDIR: u16 = 0o040000;
+const S_IFLNK: u16 = 0o120000;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_at(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+/// ExtImage
+///
+/// An ext2, ext3 or ext4 filesystem image read directly from a file.  Only
+/// the superblock, group descriptors, inodes and directory blocks are read;
+/// file contents never are.  Directories already opened are remembered, as
+/// a corrupt image may link a directory below itself.
+pub struct ExtImage {
+    file: File,
+    block_size: u64,
+    block_count: u64,
+    inode_size: u64,
+    inodes_per_group: u64,
+    filetype: bool,
+    inode_tables: Vec<u64>,
+    visited: RefCell<HashSet<u32>>,
+    pub fstype: &'static str,
+    pub info: FilesystemInfo,
+    pub dev: u64,
+}
+
+/// Inode
+///
+/// The fields of an on-disk inode needed to list and size it
+pub struct Inode {
+    pub ino: u32,
+    pub mode: u16,
+    pub size: u64,
+    pub nlink: u64,
+    pub uid: u32,
+    pub blocks: u64,
+    pub mtime: i64,
+    pub crtime: Option<i64>,
+    flags: u32,
+    block: [u8; 60],
+}
+
+/// ImageEntry
+///
+/// A name and inode read from a directory of the image
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageEntry {
+    pub name: OsString,
+    pub ino: u32,
+}
+
+/// ImageDir
+///
+/// An open directory of an image
+pub struct ImageDir {
+    meta: Meta,
+    entries: std::vec::IntoIter<ImageEntry>,
+}
+
+impl ExtImage {
+    /// open
+    ///
+    /// Read the superblock and group descriptors of an image.  The block
+    /// count and descriptor table are checked against the length of the
+    /// file before anything is sized by them.
+    pub fn open(path: &Path) -> io::Result<ExtImage> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut sb = [0u8; SUPERBLOCK_SIZE];
+        read_exact_at(&file, &mut sb, SUPERBLOCK_OFFSET)?;
+        if u16_at(&sb, 0x38) != MAGIC {
+            return Err(invalid("not an ext2/3/4 filesystem"));
+        }
+
+        let log_block_size = u32_at(&sb, 0x18);
+        if log_block_size > 6 {
+            return Err(invalid("unsupported block size"));
+        }
+        let block_size = 1024u64 << log_block_size;
+        let compat = u32_at(&sb, 0x5C);
+        let incompat = u32_at(&sb, 0x60);
+        let wide = incompat & INCOMPAT_64BIT != 0;
+        let high = |offset| {
+            if wide {
+                u64::from(u32_at(&sb, offset)) << 32
+            } else {
+                0
+            }
+        };
+
+        let inodes = u64::from(u32_at(&sb, 0x00));
+        let blocks = u64::from(u32_at(&sb, 0x04)) | high(0x150);
+        let reserved = u64::from(u32_at(&sb, 0x08)) | high(0x154);
+        let free_blocks = u64::from(u32_at(&sb, 0x0C)) | high(0x158);
+        let free_inodes = u64::from(u32_at(&sb, 0x10));
+        let first_data_block = u64::from(u32_at(&sb, 0x14));
+        let blocks_per_group = u64::from(u32_at(&sb, 0x20));
+        let inodes_per_group = u64::from(u32_at(&sb, 0x28));
+        let inode_size = if u32_at(&sb, 0x4C) == 0 {
+            128
+        } else {
+            u64::from(u16_at(&sb, 0x58))
+        };
+        let desc_size = if wide {
+            u64::from(u16_at(&sb, 0xFE)).max(32)
+        } else {
+            32
+        };
+        if blocks_per_group == 0 || inodes_per_group == 0 || inode_size < 128 {
+            return Err(invalid("corrupt superblock"));
+        }
+
+        let corrupt = || invalid("corrupt superblock");
+        blocks
+            .checked_mul(block_size)
+            .filter(|size| *size <= length)
+            .ok_or_else(corrupt)?;
+        let groups = blocks
+            .checked_sub(first_data_block)
+            .ok_or_else(corrupt)?
+            .div_ceil(blocks_per_group);
+        let table = groups
+            .checked_mul(desc_size)
+            .filter(|table| *table <= length)
+            .ok_or_else(corrupt)?;
+        let mut descriptors = vec![0u8; table as usize];
+        read_exact_at(&file, &mut descriptors, (first_data_block + 1) * block_size)?;
+        let inode_tables = descriptors
+            .chunks(desc_size as usize)
+            .map(|desc| {
+                let hi = if desc_size >= 64 {
+                    u64::from(u32_at(desc, 0x28)) << 32
+                } else {
+                    0
+                };
+                u64::from(u32_at(desc, 0x08)) | hi
+            })
+            .collect();
+
+        let fstype = if incompat & (INCOMPAT_EXTENTS | INCOMPAT_64BIT | INCOMPAT_FLEX_BG) != 0 {
+            "ext4"
+        } else if compat & COMPAT_HAS_JOURNAL != 0 {
+            "ext3"
+        } else {
+            "ext2"
+        };
+        let info = FilesystemInfo {
+            total: blocks * block_size,
+            used: blocks.saturating_sub(free_blocks) * block_size,
+            free: free_blocks.saturating_sub(reserved) * block_size,
+            reserved: reserved.min(free_blocks) * block_size,
+            inodes,
+            inodes_used: inodes.saturating_sub(free_inodes),
+        };
+
+        Ok(ExtImage {
+            dev: device_of(&file),
+            file,
+            block_size,
+            block_count: blocks,
+            inode_size,
+            inodes_per_group,
+            filetype: incompat & INCOMPAT_FILETYPE != 0,
+            inode_tables,
+            visited: RefCell::new(HashSet::new()),
+            fstype,
+            info,
+        })
+    }
+
+    /// inode
+    ///
+    /// Read an inode by number
+    pub fn inode(&self, ino: u32) -> io::Result<Inode> {
+        let index = u64::from(ino)
+            .checked_sub(1)
+            .ok_or_else(|| invalid("inode 0"))?;
+        let table = self
+            .inode_tables
+            .get((index / self.inodes_per_group) as usize)
+            .ok_or_else(|| invalid("inode out of range"))?;
+        let offset = table
+            .checked_mul(self.block_size)
+            .and_then(|start| start.checked_add((index % self.inodes_per_group) * self.inode_size))
+            .ok_or_else(|| invalid("corrupt group descriptor"))?;
+        let mut buf = vec![0u8; self.inode_size as usize];
+        read_exact_at(&self.file, &mut buf, offset)?;
+
+        let mut block = [0u8; 60];
+        block.copy_from_slice(&buf[0x28..0x28 + 60]);
+        let extra = if buf.len() > 0x82 {
+            usize::from(u16_at(&buf, 0x80))
+        } else {
+            0
+        };
+        let flags = u32_at(&buf, 0x20);
+        let mut blocks = u64::from(u32_at(&buf, 0x1C)) | u64::from(u16_at(&buf, 0x74)) << 32;
+        if flags & HUGE_FILE_FL != 0 {
+            blocks *= self.block_size / 512;
+        }
+        Ok(Inode {
+            ino,
+            mode: u16_at(&buf, 0x00),
+            size: u64::from(u32_at(&buf, 0x04)) | u64::from(u32_at(&buf, 0x6C)) << 32,
+            nlink: u64::from(u16_at(&buf, 0x1A)),
+            // the high half of the owner is in the os dependent fields
+            uid: u32::from(u16_at(&buf, 0x02)) | u32::from(u16_at(&buf, 0x78)) << 16,
+            blocks,
+            // seconds are signed 32 bit with two more epoch bits in the extra fields
+            mtime: i64::from(u32_at(&buf, 0x10) as i32)
+                + if extra >= 0x0C {
+                    i64::from(u32_at(&buf, 0x88) & 3) << 32
+                } else {
+                    0
+                },
+            crtime: (extra >= 0x18 && buf.len() >= 0x98).then(|| {
+                i64::from(u32_at(&buf, 0x90) as i32) + (i64::from(u32_at(&buf, 0x94) & 3) << 32)
+            }),
+            flags,
+            block,
+        })
+    }
+
+    /// read_dir
+    ///
+    /// The entries of a directory, without . and ..  Hash tree directories
+    /// are read linearly since their index blocks look like empty entries.
+    pub fn read_dir(&self, dir: &Inode) -> io::Result<Vec<ImageEntry>> {
+        if dir.flags & INLINE_DATA_FL != 0 {
+            // the parent inode comes first, then the entries
+            return parse_dir_block(&dir.block[4..], self.filetype);
+        }
+        let mut entries = vec![];
+        let mut buf = vec![0u8; self.block_size as usize];
+        for block in self.blocks(dir)?.into_iter().flatten() {
+            self.read_block(&mut buf, block)?;
+            entries.extend(parse_dir_block(&buf, self.filetype)?);
+        }
+        Ok(entries)
+    }
+
+    /// blocks
+    ///
+    /// Physical block of each logical block of an inode.  Holes are None.
+    /// Only directories are mapped, so one larger than the image is corrupt.
+    fn blocks(&self, inode: &Inode) -> io::Result<Vec<Option<u64>>> {
+        let count = inode.size.div_ceil(self.block_size);
+        if count > self.block_count {
+            return Err(invalid("corrupt directory"));
+        }
+        let count = count as usize;
+        let mut blocks = vec![None; count];
+        if inode.flags & EXTENTS_FL != 0 {
+            self.extents(&inode.block, &mut blocks, 0)?;
+        } else {
+            for (logical, physical) in blocks.iter_mut().take(12).enumerate() {
+                *physical = nonzero(u64::from(u32_at(&inode.block, logical * 4)));
+            }
+            let mut logical = 12;
+            for (level, slot) in [(1, 12), (2, 13), (3, 14)] {
+                if logical >= count {
+                    break;
+                }
+                let block = u64::from(u32_at(&inode.block, slot * 4));
+                self.indirect(block, level, &mut logical, &mut blocks)?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// extents
+    ///
+    /// Walk an extent tree node, either the 60 bytes of i_block or a block
+    fn extents(&self, node: &[u8], blocks: &mut [Option<u64>], depth: u32) -> io::Result<()> {
+        if node.len() < 12 || u16_at(node, 0) != EXTENT_MAGIC || depth > 5 {
+            return Err(invalid("corrupt extent tree"));
+        }
+        let entries = usize::from(u16_at(node, 2));
+        let leaf = u16_at(node, 6) == 0;
+        for index in 0..entries {
+            let entry = 12 + index * 12;
+            if entry + 12 > node.len() {
+                return Err(invalid("corrupt extent tree"));
+            }
+            if leaf {
+                let first = u32_at(node, entry) as usize;
+                let mut length = usize::from(u16_at(node, entry + 4));
+                if length > 32768 {
+                    // uninitialized extent
+                    length -= 32768;
+                }
+                let start =
+                    u64::from(u16_at(node, entry + 6)) << 32 | u64::from(u32_at(node, entry + 8));
+                for offset in 0..length {
+                    if let Some(slot) = blocks.get_mut(first + offset) {
+                        *slot = Some(start + offset as u64);
+                    }
+                }
+            } else {
+                let child =
+                    u64::from(u16_at(node, entry + 8)) << 32 | u64::from(u32_at(node, entry + 4));
+                let mut buf = vec![0u8; self.block_size as usize];
+                self.read_block(&mut buf, child)?;
+                self.extents(&buf, blocks, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// indirect
+    ///
+    /// Map the blocks below an indirect block of the given level
+    fn indirect(
+        &self,
+        block: u64,
+        level: u32,
+        logical: &mut usize,
+        blocks: &mut [Option<u64>],
+    ) -> io::Result<()> {
+        let per_block = (self.block_size / 4) as usize;
+        if block == 0 {
+            *logical += per_block.pow(level);
+            return Ok(());
+        }
+        let mut buf = vec![0u8; self.block_size as usize];
+        self.read_block(&mut buf, block)?;
+        for index in 0..per_block {
+            if *logical >= blocks.len() {
+                break;
+            }
+            let child = u64::from(u32_at(&buf, index * 4));
+            if level == 1 {
+                blocks[*logical] = nonzero(child);
+                *logical += 1;
+            } else {
+                self.indirect(child, level - 1, logical, blocks)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// read_block
+    ///
+    /// Read a block by number, which must be inside the image
+    fn read_block(&self, buf: &mut [u8], block: u64) -> io::Result<()> {
+        let offset = block
+            .checked_mul(self.block_size)
+            .filter(|_| block < self.block_count)
+            .ok_or_else(|| invalid("block out of range"))?;
+        read_exact_at(&self.file, buf, offset)
+    }
+}
+
+impl ExtImage {
+    /// open_inode
+    ///
+    /// Open a directory by inode.  A directory opened before is refused, so
+    /// a directory linked below itself is not entered again and again.
+    fn open_inode(&self, ino: u64) -> io::Result<ImageDir> {
+        let ino = u32::try_from(ino).map_err(|_| invalid("inode out of range"))?;
+        let inode = self.inode(ino)?;
+        let meta = inode.meta(self.dev);
+        if meta.kind != Kind::Directory {
+            return Err(io::Error::new(
+                io::ErrorKind::NotADirectory,
+                "not a directory",
+            ));
+        }
+        if !self.visited.borrow_mut().insert(ino) {
+            return Err(invalid("corrupt directory, already visited"));
+        }
+        Ok(ImageDir {
+            meta,
+            entries: self.read_dir(&inode)?.into_iter(),
+        })
+    }
+}
+
+/// The image is the only anchor, so its paths are ignored and the root
+/// directory is opened.
+impl FsBackend for ExtImage {
+    type Dir = ImageDir;
+
+    fn open(&self, _path: &Path) -> io::Result<ImageDir> {
+        self.open_inode(u64::from(ROOT_INODE))
+    }
+
+    fn open_at(&self, _dir: &ImageDir, entry: &DirEntry) -> io::Result<ImageDir> {
+        self.open_inode(entry.ino)
+    }
+
+    fn read_dir(&self, dir: &mut ImageDir) -> Option<io::Result<DirEntry>> {
+        let entry = dir.entries.next()?;
+        Some(DirEntry::new(&entry.name, u64::from(entry.ino), None))
+    }
+
+    fn stat(&self, _path: &Path) -> io::Result<Meta> {
+        Ok(self.inode(ROOT_INODE)?.meta(self.dev))
+    }
+
+    fn dir_metadata(&self, dir: &ImageDir) -> io::Result<Meta> {
+        Ok(dir.meta.clone())
+    }
+
+    fn metadata(&self, _dir: &ImageDir, entries: &[DirEntry]) -> Vec<io::Result<Meta>> {
+        entries
+            .iter()
+            .map(|entry| {
+                let ino = u32::try_from(entry.ino).map_err(|_| invalid("inode out of range"))?;
+                Ok(self.inode(ino)?.meta(self.dev))
+            })
+            .collect()
+    }
+
+    fn device(&self, _path: &Path) -> u64 {
+        self.dev
+    }
+
+    fn filesystem(&self, _path: &Path) -> io::Result<FilesystemInfo> {
+        Ok(self.info.clone())
+    }
+}
+
+impl Inode {
+    /// meta
+    ///
+    /// The inode as the walker sees a stat of a file
+    pub fn meta(&self, dev: u64) -> Meta {
+        let kind = match self.mode & S_IFMT {
+            S_IFREG => Kind::File,
+            S_IFDIR => Kind::Directory,
+            S_IFLNK => Kind::Symlink,
+            _ => Kind::Other,
+        };
+        Meta {
+            kind,
+            dev,
+            ino: u64::from(self.ino),
+            nlink: self.nlink,
+            uid: self.uid,
+            size: self.size,
+            blocks: self.blocks,
+            mtime: self.mtime,
+            btime: self.crtime,
+            mnt_id: None,
+        }
+    }
+}
+
+fn nonzero(block: u64) -> Option<u64> {
+    if block == 0 {
+        None
+    } else {
+        Some(block)
+    }
+}
+
+/// parse_dir_block
+///
+/// Parse the linear directory entries of a block.  Entries with inode 0,
+/// such as hash tree index blocks and checksum tails, are skipped.
+fn parse_dir_block(buf: &[u8], filetype: bool) -> io::Result<Vec<ImageEntry>> {
+    let mut entries = vec![];
+    let mut offset = 0;
+    while offset + 8 <= buf.len() {
+        let ino = u32_at(buf, offset);
+        let mut length = usize::from(u16_at(buf, offset + 4));
+        if length == 0 || length == 65535 {
+            // 64KiB blocks store their length this way
+            length = buf.len() - offset;
+        }
+        let name_length = if filetype {
+            usize::from(buf[offset + 6])
+        } else {
+            usize::from(u16_at(buf, offset + 6))
+        };
+        if length < 8 || offset + length > buf.len() || 8 + name_length > length {
+            return Err(invalid("corrupt directory"));
+        }
+        let name = &buf[offset + 8..offset + 8 + name_length];
+        if ino != 0 && name != b"." && name != b".." {
+            entries.push(ImageEntry {
+                name: os_string(name),
+                ino,
+            });
+        }
+        offset += length;
+    }
+    Ok(entries)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn os_string(name: &[u8]) -> OsString {
+    OsString::from_vec(name.to_vec())
+}
+
+#[cfg(target_os = "windows")]
+fn os_string(name: &[u8]) -> OsString {
+    OsString::from(String::from_utf8_lossy(name).to_string())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(target_os = "windows")]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// device_of
+///
+/// Hard links are told apart by device and inode.  The inode of the image
+/// file stands in for the device so several images do not collide.
+#[cfg(not(target_os = "windows"))]
+fn device_of(file: &File) -> u64 {
+    file.metadata().map(|metadata| metadata.ino()).unwrap_or(0)
+}
+
+#[cfg(target_os = "windows")]
+fn device_of(_file: &File) -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(buf: &mut Vec<u8>, ino: u32, length: u16, name: &[u8], file_type: u8) {
+        buf.extend(ino.to_le_bytes());
+        buf.extend(length.to_le_bytes());
+        buf.push(name.len() as u8);
+        buf.push(file_type);
+        buf.extend(name);
+        buf.resize(buf.len() + usize::from(length) - 8 - name.len(), 0);
+    }
+
+    #[test]
+    fn dir_block() {
+        let mut buf = vec![];
+        entry(&mut buf, 2, 12, b".", 2);
+        entry(&mut buf, 2, 12, b"..", 2);
+        entry(&mut buf, 12, 16, b"file", 1);
+        entry(&mut buf, 0, 16, b"gone", 1);
+        entry(&mut buf, 13, 1024 - 56, b"dir", 2);
+
+        let entries = parse_dir_block(&buf, true).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, OsString::from("file"));
+        assert_eq!(entries[0].ino, 12);
+        assert_eq!(entries[1].name, OsString::from("dir"));
+    }
+
+    #[test]
+    fn dir_block_corrupt() {
+        let mut buf = vec![];
+        entry(&mut buf, 12, 16, b"file", 1);
+        buf[4] = 4;
+        assert!(parse_dir_block(&buf, true).is_err());
+    }
+
+    #[test]
+    fn not_an_image() {
+        let path = std::env::temp_dir().join("ds-not-an-image");
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        assert!(ExtImage::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// An image of length bytes whose superblock has the given fields, each
+    /// an offset and a little endian value
+    fn superblock(name: &str, length: usize, fields: &[(usize, u32)]) -> std::path::PathBuf {
+        let mut image = vec![0u8; length];
+        let sb = SUPERBLOCK_OFFSET as usize;
+        image[sb + 0x38..sb + 0x3A].copy_from_slice(&MAGIC.to_le_bytes());
+        for (offset, value) in fields {
+            image[sb + offset..sb + offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, image).unwrap();
+        path
+    }
+
+    #[test]
+    fn corrupt_superblock() {
+        let open = |name, length, fields: &[(usize, u32)]| {
+            let path = superblock(name, length, fields);
+            let opened = ExtImage::open(&path)
+                .map(|_| ())
+                .map_err(|err| err.to_string());
+            std::fs::remove_file(&path).unwrap();
+            opened
+        };
+        let corrupt = Err("corrupt superblock".to_string());
+        // blocks, first data block, blocks and inodes per group
+        let valid = [(0x04, 8), (0x14, 1), (0x20, 8192), (0x28, 16)];
+        assert_eq!(open("ds-image-valid", 8192, &valid), Ok(()));
+        // truncated to fewer bytes than the blocks it claims
+        assert_eq!(open("ds-image-truncated", 4096, &valid), corrupt);
+        // fewer blocks than the first data block
+        let mut fields = valid;
+        fields[0] = (0x04, 0);
+        assert_eq!(open("ds-image-first-block", 8192, &fields), corrupt);
+        // a block count far past the file
+        fields[0] = (0x04, u32::MAX);
+        assert_eq!(open("ds-image-blocks", 8192, &fields), corrupt);
+        // 64 bit descriptors of 64KiB for each block, longer than the file
+        let mut fields = valid.to_vec();
+        fields[2] = (0x20, 1);
+        fields.extend([(0x60, INCOMPAT_64BIT), (0xFE, 0xFFFF)]);
+        assert_eq!(open("ds-image-descriptors", 8192, &fields), corrupt);
+    }
+}
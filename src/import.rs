@@ -0,0 +1,212 @@
+use clap::ArgMatches;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// import
+///
+/// Read the path to size map of ds report from --import-du or --import-ncdu.
+/// A file of - reads stdin.  Exits when the file cannot be read or parsed.
+pub fn import(matches: &ArgMatches) -> BTreeMap<String, u64> {
+    let du = matches.value_of("import-du");
+    let file = match du.or(matches.value_of("import-ncdu")) {
+        Some(file) => file,
+        None => return BTreeMap::new(),
+    };
+    let reader: Box<dyn BufRead> = if file == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        match File::open(file) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Error: {} {}", file, err);
+                process::exit(1);
+            }
+        }
+    };
+    let result = match du {
+        Some(_) => import_du(reader),
+        None => import_ncdu(reader),
+    };
+    match result {
+        Ok(disk_space) => disk_space,
+        Err(err) => {
+            eprintln!("Error: {} {}", file, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// import_du
+///
+/// Parse the output of du -ab, a size in bytes and a path separated by a tab
+/// on each line.  Directories already hold the sizes of their contents.
+pub fn import_du<R: BufRead>(reader: R) -> io::Result<BTreeMap<String, u64>> {
+    let mut disk_space = BTreeMap::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {} is not SIZE<tab>PATH", number + 1),
+            )
+        };
+        let (size, path) = line.split_once('\t').ok_or_else(invalid)?;
+        let size = size.trim().parse().map_err(|_| invalid())?;
+        disk_space.insert(path.to_string(), size);
+    }
+    Ok(disk_space)
+}
+
+/// import_ncdu
+///
+/// Parse an ncdu JSON export, as written by ncdu -o.  Sizes are apparent
+/// sizes; each directory holds its own size and that of its contents.
+/// Hard links are counted once and excluded entries are left out.
+pub fn import_ncdu<R: Read>(reader: R) -> io::Result<BTreeMap<String, u64>> {
+    let export: Value = serde_json::from_reader(reader)?;
+    let root = match export.as_array() {
+        Some(export) if export.first().and_then(Value::as_u64) == Some(1) && export.len() > 3 => {
+            &export[3]
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an ncdu export of major version 1",
+            ))
+        }
+    };
+    let mut ncdu = Ncdu {
+        disk_space: BTreeMap::new(),
+        links: BTreeSet::new(),
+    };
+    ncdu.directory(root, Path::new(""), 0)?;
+    Ok(ncdu.disk_space)
+}
+
+struct Ncdu {
+    disk_space: BTreeMap<String, u64>,
+    links: BTreeSet<(u64, u64)>,
+}
+
+impl Ncdu {
+    /// directory
+    ///
+    /// Record a directory, an array of its own entry followed by its
+    /// children, and return its total.  The root entry names a full path.
+    fn directory(&mut self, dir: &Value, parent: &Path, dev: u64) -> io::Result<u64> {
+        let entries = dir.as_array().filter(|entries| !entries.is_empty());
+        let entries = entries.ok_or_else(|| invalid("empty directory array"))?;
+        let (dir_path, dev, mut total) = match self.entry(&entries[0], parent, dev)? {
+            Some(entry) => entry,
+            None => return Ok(0),
+        };
+        for child in &entries[1..] {
+            total += if child.is_array() {
+                self.directory(child, &dir_path, dev)?
+            } else {
+                self.entry(child, &dir_path, dev)?
+                    .map_or(0, |(_, _, size)| size)
+            };
+        }
+        self.disk_space
+            .insert(dir_path.to_string_lossy().to_string(), total);
+        Ok(total)
+    }
+
+    /// entry
+    ///
+    /// Read the path, device and counted size of an entry and record it.
+    /// Directories are recorded again once their children are summed.  None
+    /// for excluded entries.
+    fn entry(
+        &mut self,
+        entry: &Value,
+        parent: &Path,
+        dev: u64,
+    ) -> io::Result<Option<(PathBuf, u64, u64)>> {
+        let name = entry["name"]
+            .as_str()
+            .ok_or_else(|| invalid("entry without a name"))?;
+        if entry.get("excluded").is_some() {
+            return Ok(None);
+        }
+        let path = parent.join(name);
+        let dev = entry["dev"].as_u64().unwrap_or(dev);
+        let size = entry["asize"].as_u64().unwrap_or(0);
+        if entry["hlnkc"].as_bool() == Some(true) {
+            if let Some(ino) = entry["ino"].as_u64() {
+                if !self.links.insert((dev, ino)) {
+                    return Ok(Some((path, dev, 0)));
+                }
+            }
+        }
+        if entry.get("asize").is_some() {
+            self.disk_space
+                .insert(path.to_string_lossy().to_string(), size);
+        }
+        Ok(Some((path, dev, size)))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn du_output() {
+        let du = "4096\t/tmp/a/b\n12\t/tmp/a/b/with\ttab\n\n8200\t/tmp/a\n";
+        let disk_space = import_du(du.as_bytes()).unwrap();
+        assert_eq!(disk_space.len(), 3);
+        assert_eq!(disk_space["/tmp/a/b/with\ttab"], 12);
+        assert_eq!(disk_space["/tmp/a"], 8200);
+    }
+
+    #[test]
+    fn du_invalid() {
+        let err = import_du("4096 /tmp\n".as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "line 1 is not SIZE<tab>PATH");
+        assert!(import_du("big\t/tmp\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn ncdu_export() {
+        let export = r#"[1,2,{"progname":"ncdu","progver":"1.19","timestamp":1700000000},
+            [{"name":"/data","asize":4096,"dev":2049,"ino":2},
+             {"name":"one","asize":100,"ino":3},
+             [{"name":"sub","asize":4096,"ino":4},
+              {"name":"link","asize":50,"ino":5,"hlnkc":true},
+              {"name":"same","asize":50,"ino":5,"hlnkc":true},
+              {"name":"skip","excluded":"pattern"}],
+             {"name":"mnt","asize":10,"dev":7,"excluded":"otherfs"},
+             {"name":"fifo","notreg":true}]]"#;
+        let disk_space = import_ncdu(export.as_bytes()).unwrap();
+        assert_eq!(disk_space["/data/one"], 100);
+        assert_eq!(disk_space["/data/sub/link"], 50);
+        assert!(!disk_space.contains_key("/data/sub/same"));
+        assert_eq!(disk_space["/data/sub"], 4146);
+        assert_eq!(disk_space["/data"], 8342);
+        assert!(!disk_space.contains_key("/data/sub/skip"));
+        assert!(!disk_space.contains_key("/data/mnt"));
+        assert!(!disk_space.contains_key("/data/fifo"));
+    }
+
+    #[test]
+    fn ncdu_invalid() {
+        assert!(import_ncdu("[2,0,{},[]]".as_bytes()).is_err());
+        assert!(import_ncdu("{}".as_bytes()).is_err());
+        assert!(import_ncdu("[1,0,{},[]]".as_bytes()).is_err());
+        assert!(import_ncdu("[1,0,{},[{\"asize\":1}]]".as_bytes()).is_err());
+    }
+}
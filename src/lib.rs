@@ -1,7 +1,43 @@
 /// lib.rs is present for integration testing
 extern crate clap;
+mod archive;
+mod backend;
 mod ds;
+mod export;
+mod filesystem;
+mod format;
+mod image;
+mod import;
+mod mounts;
+mod progress;
 mod report;
+mod s3;
+mod signals;
+mod template;
+mod terminal;
+mod tree;
+mod units;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod walk;
 
+pub use archive::*;
+pub use backend::*;
 pub use ds::*;
+pub use export::*;
+pub use filesystem::*;
+pub use format::*;
+pub use image::*;
+pub use import::*;
+pub use mounts::*;
+pub use progress::*;
 pub use report::*;
+pub use s3::*;
+pub use signals::*;
+pub use template::*;
+pub use terminal::*;
+pub use tree::*;
+pub use units::*;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub use uring::*;
+pub use walk::*;
@@ -1,15 +1,53 @@
 extern crate clap;
+mod archive;
+mod backend;
 mod cli;
 mod ds;
+mod export;
+mod filesystem;
+mod format;
+mod image;
+mod import;
+mod mounts;
+mod progress;
 mod report;
+mod s3;
+mod signals;
+mod template;
+mod terminal;
+mod tree;
+mod units;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+mod walk;
 
-use crate::ds::DSGroup;
+use crate::ds::{DSGroup, Partial};
+use std::process;
 
 fn main() {
     let matches = cli::get_matches();
+    if let Some(matches) = matches.subcommand_matches("report") {
+        let disk_space = import::import(matches);
+        report::report(disk_space, &DSGroup::new(), matches);
+        return;
+    }
     let anchors: Vec<_> = cli::get_dirs(&matches);
     let mut group = DSGroup::new();
 
-    let disk_space = group.calculate(&anchors, &matches);
-    report::report(disk_space, &matches);
+    signals::install();
+    let disk_space = match matches.value_of("files-from") {
+        Some(files_from) => group.calculate_files(files_from, &matches),
+        None => group.calculate(&anchors, &matches),
+    };
+    signals::restore();
+    if let Some(file) = matches.value_of("export-ncdu") {
+        export::export(file, &group);
+    }
+    if matches.value_of("export-ncdu") != Some("-") {
+        report::report(disk_space, &group, &matches);
+    }
+
+    if group.budget.exhausted == Some(Partial::Interrupted) {
+        process::exit(signals::INTERRUPTED_STATUS);
+    }
 }
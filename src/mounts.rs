@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Virtual filesystems skipped unless requested with --include-fstype.  Their
+/// contents are generated by the kernel and do not consume disk space.
+pub const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "sysfs",
+    "tracefs",
+];
+
+/// MountEntry
+///
+/// A single line of /proc/self/mountinfo
+#[derive(Clone, Debug, PartialEq)]
+pub struct MountEntry {
+    pub id: u64,
+    pub parent: u64,
+    pub major: u32,
+    pub minor: u32,
+    pub mount_point: PathBuf,
+    pub fstype: String,
+    pub source: String,
+}
+
+/// MountTable
+///
+/// Mount points and filesystem types of the running system.  The mount
+/// points below each anchor are kept by the path traversal will reach them
+/// under, so crossing a mount is a map lookup.
+pub struct MountTable {
+    pub entries: Vec<MountEntry>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    points: BTreeMap<PathBuf, usize>,
+}
+
+impl Default for MountTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MountTable {
+    pub fn new() -> MountTable {
+        MountTable {
+            entries: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            points: BTreeMap::new(),
+        }
+    }
+
+    /// load
+    ///
+    /// Read the mount table.  Other platforms have no mount table and every
+    /// directory is traversed.
+    #[cfg(target_os = "linux")]
+    pub fn load(&mut self) {
+        if let Ok(contents) = fs::read_to_string("/proc/self/mountinfo") {
+            self.entries = parse(&contents);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn load(&mut self) {}
+
+    /// anchor
+    ///
+    /// Map the mount points below an anchor to the paths traversal will
+    /// produce for them.  Later entries hide earlier ones mounted on the
+    /// same directory.
+    pub fn anchor(&mut self, anchor: &Path) {
+        let canonical = match anchor.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(_) => return,
+        };
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Ok(relative) = entry.mount_point.strip_prefix(&canonical) {
+                if relative.as_os_str().is_empty() {
+                    self.points.insert(anchor.to_path_buf(), index);
+                } else {
+                    self.points.insert(anchor.join(relative), index);
+                }
+            }
+        }
+    }
+
+    /// add_source
+    ///
+    /// Record a filesystem image or bucket read in place of a mount on its own
+    /// path, so it is reported with its filesystem type.
+    pub fn add_source(&mut self, path: &Path, fstype: &str) {
+        self.entries.push(MountEntry {
+            id: 0,
+            parent: 0,
+            major: 0,
+            minor: 0,
+            mount_point: path.to_path_buf(),
+            fstype: fstype.to_string(),
+            source: path.to_string_lossy().to_string(),
+        });
+        self.points
+            .insert(path.to_path_buf(), self.entries.len() - 1);
+    }
+
+    /// mount_point
+    ///
+    /// Returns the filesystem mounted on path if path is a mount point below
+    /// an anchor.
+    pub fn mount_point(&self, path: &Path) -> Option<&MountEntry> {
+        self.points.get(path).map(|&index| &self.entries[index])
+    }
+
+    /// filesystem_type
+    ///
+    /// Returns the type of the filesystem holding path.  The mount with the
+    /// longest matching prefix wins, later entries winning ties.
+    pub fn filesystem_type(&self, path: &Path) -> Option<&str> {
+        if let Some(entry) = self.mount_point(path) {
+            return Some(&entry.fstype);
+        }
+        let canonical = path.canonicalize().ok()?;
+        self.entries
+            .iter()
+            .filter(|entry| canonical.starts_with(&entry.mount_point))
+            .max_by_key(|entry| entry.mount_point.components().count())
+            .map(|entry| entry.fstype.as_str())
+    }
+
+    /// skip
+    ///
+    /// Check whether a filesystem type is traversed.  An include list admits
+    /// only the listed types.  Otherwise excluded and virtual filesystems are
+    /// skipped.
+    pub fn skip(&self, fstype: &str) -> bool {
+        if self.exclude.iter().any(|x| x == fstype) {
+            return true;
+        }
+        if !self.include.is_empty() {
+            return !self.include.iter().any(|x| x == fstype);
+        }
+        PSEUDO_FILESYSTEMS.contains(&fstype)
+    }
+
+    /// skip_anchor
+    ///
+    /// Check whether the filesystem holding an anchor is traversed.  Excluded
+    /// and virtual filesystems are skipped as mounts are, but an include list
+    /// only limits the mounts crossed into, so it admits any other anchor.
+    pub fn skip_anchor(&self, fstype: &str) -> bool {
+        self.exclude.iter().any(|x| x == fstype)
+            || (PSEUDO_FILESYSTEMS.contains(&fstype) && !self.include.iter().any(|x| x == fstype))
+    }
+}
+
+/// parse
+///
+/// Parse the contents of /proc/self/mountinfo.  Malformed lines are ignored.
+///
+///   36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw
+pub fn parse(contents: &str) -> Vec<MountEntry> {
+    contents.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<MountEntry> {
+    let fields: Vec<&str> = line.split(' ').collect();
+    let separator = fields.iter().position(|&x| x == "-")?;
+    if separator < 6 || fields.len() < separator + 3 {
+        return None;
+    }
+    let mut device = fields[2].split(':');
+
+    Some(MountEntry {
+        id: fields[0].parse().ok()?,
+        parent: fields[1].parse().ok()?,
+        major: device.next()?.parse().ok()?,
+        minor: device.next()?.parse().ok()?,
+        mount_point: PathBuf::from(unescape(fields[4])),
+        fstype: unescape(fields[separator + 1]),
+        source: unescape(fields[separator + 2]),
+    })
+}
+
+/// unescape
+///
+/// The kernel writes space, tab, newline and backslash as three digit octal
+/// escapes.
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && i + 3 < bytes.len()
+            && bytes[i + 1..i + 4]
+                .iter()
+                .all(|x| (b'0'..=b'7').contains(x))
+        {
+            let octal = &field[i + 1..i + 4];
+            if let Ok(value) = u8::from_str_radix(octal, 8) {
+                result.push(value);
+                i += 4;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+23 28 0:22 / /proc rw,relatime - proc proc rw
+28 1 254:1 / / rw,relatime - ext4 /dev/vda1 rw
+40 28 254:2 / /home rw,relatime shared:1 - ext4 /dev/vda2 rw
+41 40 0:45 / /home/user/remote\\040dir rw - fuse.sshfs host:/ rw
+bad line
+";
+
+    #[test]
+    fn parse_mountinfo() {
+        let entries = parse(MOUNTINFO);
+        assert_eq!(entries.len(), 4);
+        assert_eq!(
+            entries[2],
+            MountEntry {
+                id: 40,
+                parent: 28,
+                major: 254,
+                minor: 2,
+                mount_point: PathBuf::from("/home"),
+                fstype: "ext4".to_string(),
+                source: "/dev/vda2".to_string(),
+            }
+        );
+        assert_eq!(
+            entries[3].mount_point,
+            PathBuf::from("/home/user/remote dir")
+        );
+        assert_eq!(entries[3].fstype, "fuse.sshfs");
+    }
+
+    #[test]
+    fn unescape_octal() {
+        assert_eq!(unescape("a\\040b\\134c"), "a b\\c");
+        assert_eq!(unescape("trailing\\04"), "trailing\\04");
+    }
+
+    #[test]
+    fn skip_defaults() {
+        let table = MountTable::new();
+        assert!(table.skip("proc"));
+        assert!(table.skip("sysfs"));
+        assert!(!table.skip("ext4"));
+        assert!(!table.skip("nfs"));
+    }
+
+    #[test]
+    fn skip_exclude() {
+        let mut table = MountTable::new();
+        table.exclude = vec!["nfs".to_string()];
+        assert!(table.skip("nfs"));
+        assert!(!table.skip("ext4"));
+    }
+
+    #[test]
+    fn skip_include() {
+        let mut table = MountTable::new();
+        table.include = vec!["ext4".to_string(), "proc".to_string()];
+        assert!(!table.skip("ext4"));
+        assert!(!table.skip("proc"));
+        assert!(table.skip("xfs"));
+    }
+
+    #[test]
+    fn skip_anchors() {
+        let mut table = MountTable::new();
+        assert!(table.skip_anchor("proc"));
+        assert!(!table.skip_anchor("ext4"));
+        table.include = vec!["nfs".to_string()];
+        assert!(!table.skip_anchor("ext4"));
+        assert!(table.skip_anchor("sysfs"));
+        table.include.push("sysfs".to_string());
+        assert!(!table.skip_anchor("sysfs"));
+        table.exclude = vec!["ext4".to_string()];
+        assert!(table.skip_anchor("ext4"));
+    }
+
+    #[test]
+    fn anchor_mount_points() {
+        let mut table = MountTable::new();
+        table.entries = parse(MOUNTINFO);
+        table.anchor(Path::new("/"));
+        assert_eq!(
+            table.mount_point(Path::new("/proc")).unwrap().fstype,
+            "proc"
+        );
+        assert_eq!(table.mount_point(Path::new("/")).unwrap().id, 28);
+        assert_eq!(table.mount_point(Path::new("/usr")), None);
+    }
+
+    #[test]
+    fn image_filesystem_type() {
+        let mut table = MountTable::new();
+        table.add_source(Path::new("disk.img"), "ext4");
+        assert_eq!(table.filesystem_type(Path::new("disk.img")), Some("ext4"));
+        assert_eq!(table.mount_point(Path::new("disk.img")).unwrap().id, 0);
+    }
+
+    #[test]
+    fn filesystem_type_mount_point() {
+        let mut table = MountTable::new();
+        table.entries = parse(MOUNTINFO);
+        table.anchor(Path::new("/"));
+        assert_eq!(table.filesystem_type(Path::new("/proc")), Some("proc"));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::report::simple_units;
+use std::io;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Clears the current terminal line
+pub const CLEAR_LINE: &str = "\r\x1b[K";
+
+/// Longest progress line, leaving room on an 80 column terminal
+const WIDTH: usize = 79;
+
+/// Progress
+///
+/// A single line on stderr updated in place during long scans.  Only shown
+/// when stderr is a terminal.  The percent complete is estimated from the
+/// used inode count of the anchor filesystems.
+pub struct Progress {
+    pub enabled: bool,
+    pub entries: u64,
+    pub bytes: u64,
+    pub estimate: u64,
+    start: Instant,
+    last: Instant,
+    drawn: bool,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        let now = Instant::now();
+        Progress {
+            enabled: io::stderr().is_terminal(),
+            entries: 0,
+            bytes: 0,
+            estimate: 0,
+            start: now,
+            last: now,
+            drawn: false,
+        }
+    }
+
+    /// visit
+    ///
+    /// Count an entry of the directory currently walked.  Redraws at most ten
+    /// times a second.
+    pub fn visit(&mut self, dir: &Path, errors: u64) {
+        self.entries += 1;
+        if !self.enabled || self.last.elapsed() < Duration::from_millis(100) {
+            return;
+        }
+        self.last = Instant::now();
+        eprint!("{}{}", CLEAR_LINE, self.line(dir, errors));
+        let _ = io::stderr().flush();
+        self.drawn = true;
+    }
+
+    /// finish
+    ///
+    /// Remove the progress line before the report is printed
+    pub fn finish(&mut self) {
+        if self.drawn {
+            eprint!("{}", CLEAR_LINE);
+            let _ = io::stderr().flush();
+            self.drawn = false;
+        }
+    }
+
+    /// line
+    ///
+    /// Entries visited, bytes counted, entries per second, errors, estimated
+    /// percent complete and as much of the current directory as fits.
+    fn line(&self, dir: &Path, errors: u64) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            (self.entries as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let mut line = format!(
+            "{} entries {} {}/s {} errors",
+            self.entries,
+            simple_units(self.bytes).trim_start(),
+            rate,
+            errors
+        );
+        if let Some(percent) = self.percent() {
+            line = format!("{} {:>2}%", line, percent);
+        }
+
+        let dir = dir.to_string_lossy();
+        let room = WIDTH.saturating_sub(line.chars().count() + 1);
+        let count = dir.chars().count();
+        if count <= room {
+            format!("{} {}", line, dir)
+        } else if room > 3 {
+            let tail: String = dir.chars().skip(count - (room - 3)).collect();
+            format!("{} ...{}", line, tail)
+        } else {
+            line
+        }
+    }
+
+    /// percent
+    ///
+    /// Estimated percent complete.  Capped at 99 since mounts crossed and
+    /// other filesystems are not part of the estimate.
+    fn percent(&self) -> Option<u64> {
+        (self.entries * 100)
+            .checked_div(self.estimate)
+            .map(|percent| percent.min(99))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visit_counts() {
+        let mut progress = Progress::new();
+        progress.enabled = false;
+        progress.visit(Path::new("/tmp"), 0);
+        progress.visit(Path::new("/tmp"), 0);
+        assert_eq!(progress.entries, 2);
+        assert!(!progress.drawn);
+    }
+
+    #[test]
+    fn line_contents() {
+        let mut progress = Progress::new();
+        progress.entries = 50;
+        progress.bytes = 2048;
+        progress.estimate = 200;
+        let line = progress.line(Path::new("/usr/lib"), 3);
+        assert!(line.starts_with("50 entries 2K "));
+        assert!(line.ends_with(" 3 errors 25% /usr/lib"));
+    }
+
+    #[test]
+    fn line_long_directory() {
+        let progress = Progress::new();
+        let dir = format!("/{}", "x".repeat(200));
+        let line = progress.line(Path::new(&dir), 0);
+        assert_eq!(line.chars().count(), WIDTH);
+        assert!(line.contains(" ...xxx"));
+    }
+
+    #[test]
+    fn percent_capped() {
+        let mut progress = Progress::new();
+        assert_eq!(progress.percent(), None);
+        progress.estimate = 10;
+        progress.entries = 5;
+        assert_eq!(progress.percent(), Some(50));
+        progress.entries = 50;
+        assert_eq!(progress.percent(), Some(99));
+    }
+}
@@ -1,20 +1,250 @@
+use crate::ds::{DSGroup, Partial};
+use crate::format::{write_rows, Format, Row};
+use crate::template::{depth, Template, PERCENT_TEMPLATE};
+use crate::terminal::{self, shorten, Stream, MIN_PATH};
+use crate::tree::Hierarchy;
+use crate::units::Units;
 #[cfg(test)]
 use clap::App;
 use clap::ArgMatches;
 extern crate colored;
 use self::colored::*;
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
 use std::io;
 #[allow(unused_imports)] // method write_all is needed
 use std::io::Write;
-use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+
+/// SortKey
+///
+/// An order of --sort.  Sizes, counts and times put the largest or newest
+/// first, names and depths the smallest.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Size,
+    Apparent,
+    Exclusive,
+    Count,
+    Mtime,
+    Name,
+    Depth,
+}
+
+impl SortKey {
+    /// parse
+    ///
+    /// Read comma separated keys, compared in turn
+    pub fn parse(value: &str) -> Result<Vec<SortKey>, String> {
+        value
+            .split(',')
+            .map(|key| match key.trim() {
+                "size" => Ok(SortKey::Size),
+                "apparent" => Ok(SortKey::Apparent),
+                "exclusive" => Ok(SortKey::Exclusive),
+                "count" => Ok(SortKey::Count),
+                "mtime" => Ok(SortKey::Mtime),
+                "name" => Ok(SortKey::Name),
+                "depth" => Ok(SortKey::Depth),
+                key => Err(format!(
+                    "{} is not one of size, apparent, exclusive, count, mtime, name or depth",
+                    key
+                )),
+            })
+            .collect()
+    }
+
+    /// scanned
+    ///
+    /// Whether the key needs the fields of the scan beyond the size
+    fn scanned(&self) -> bool {
+        matches!(
+            self,
+            SortKey::Apparent | SortKey::Exclusive | SortKey::Count | SortKey::Mtime
+        )
+    }
+
+    fn compare(&self, a: &Sortable, b: &Sortable) -> Ordering {
+        match self {
+            SortKey::Size => b.row.size.cmp(&a.row.size),
+            SortKey::Apparent => b.row.apparent.cmp(&a.row.apparent),
+            SortKey::Exclusive => b.row.exclusive.cmp(&a.row.exclusive),
+            SortKey::Count => b.row.count.cmp(&a.row.count),
+            SortKey::Mtime => b.row.mtime.cmp(&a.row.mtime),
+            SortKey::Name => a.row.path.cmp(b.row.path),
+            SortKey::Depth => a.depth.cmp(&b.depth),
+        }
+    }
+}
+
+/// Filter
+///
+/// The entries kept by --files, --dirs or --leaves, the directories without
+/// subdirectories
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    All,
+    Files,
+    Dirs,
+    Leaves,
+}
+
+/// Tree
+///
+/// The directories among the entries, those holding other directories and
+/// the size of the largest child of each.  A scan knows its directories; for
+/// ds report they are the parents of other entries.
+struct Tree {
+    dirs: BTreeSet<PathBuf>,
+    branches: BTreeSet<PathBuf>,
+    largest: BTreeMap<PathBuf, u64>,
+}
+
+impl Tree {
+    fn new(group: &DSGroup, disk_space: &BTreeMap<String, u64>) -> Tree {
+        let dirs: BTreeSet<PathBuf> = if group.dirs.is_empty() {
+            disk_space
+                .keys()
+                .filter_map(|filename| Path::new(filename).parent())
+                .map(Path::to_path_buf)
+                .collect()
+        } else {
+            group.dirs.keys().cloned().collect()
+        };
+        let branches = dirs
+            .iter()
+            .filter_map(|dir| dir.parent())
+            .filter(|parent| dirs.contains(*parent))
+            .map(Path::to_path_buf)
+            .collect();
+        let mut largest: BTreeMap<PathBuf, u64> = BTreeMap::new();
+        for (filename, size) in disk_space {
+            if let Some(parent) = Path::new(filename).parent() {
+                let child = largest.entry(parent.to_path_buf()).or_default();
+                *child = (*child).max(*size);
+            }
+        }
+        Tree {
+            dirs,
+            branches,
+            largest,
+        }
+    }
+
+    /// keeps
+    ///
+    /// Whether the entry passes the filter and is not collapsed into a child
+    /// holding more than the share of it
+    fn keeps(&self, rs: &ReportSettings, filename: &str, size: u64) -> bool {
+        let path = Path::new(filename);
+        if let (Some(share), Some(child)) = (rs.collapse, self.largest.get(path)) {
+            if *child as f64 > size as f64 * share / 100.0 {
+                return false;
+            }
+        }
+        match rs.filter {
+            Filter::All => true,
+            Filter::Files => !self.dirs.contains(path),
+            Filter::Dirs => self.dirs.contains(path),
+            Filter::Leaves => self.dirs.contains(path) && !self.branches.contains(path),
+        }
+    }
+}
+
+/// ColorWhen
+///
+/// When the size column is colored, from --color.  None is the former
+/// spelling of never.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorWhen {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    pub fn parse(value: &str) -> Result<ColorWhen, String> {
+        match value {
+            "auto" => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            "never" | "none" => Ok(ColorWhen::Never),
+            value => Err(format!("{} is not one of auto, always or never", value)),
+        }
+    }
+
+    /// enabled
+    ///
+    /// Whether to color.  Auto colors when the stream of the report is a
+    /// terminal, unless NO_COLOR is set and not empty or CLICOLOR is 0.
+    /// CLICOLOR_FORCE other than 0 colors output to anything and takes
+    /// precedence.
+    fn enabled(&self, var: &dyn Fn(&str) -> Option<String>, terminal: bool) -> bool {
+        let set = |name: &str| var(name).filter(|value| !value.is_empty());
+        match self {
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+            ColorWhen::Auto if set("CLICOLOR_FORCE").is_some_and(|value| value != "0") => true,
+            ColorWhen::Auto if set("NO_COLOR").is_some() => false,
+            ColorWhen::Auto if set("CLICOLOR").as_deref() == Some("0") => false,
+            ColorWhen::Auto => terminal,
+        }
+    }
+}
+
+/// Theme
+///
+/// The color of the size column from --theme, or the former -c COLOR
+pub fn parse_theme(value: &str) -> Result<Color, String> {
+    match value {
+        "black" => Ok(Color::Black),
+        "red" => Ok(Color::Red),
+        "green" => Ok(Color::Green),
+        "yellow" => Ok(Color::Yellow),
+        "blue" => Ok(Color::Blue),
+        "magenta" => Ok(Color::Magenta),
+        "cyan" => Ok(Color::Cyan),
+        "white" => Ok(Color::White),
+        value => Err(format!(
+            "{} is not one of black, red, green, yellow, blue, magenta, cyan or white",
+            value
+        )),
+    }
+}
+
+/// Sortable
+///
+/// An entry with the fields its keys compare
+struct Sortable<'a> {
+    row: Row<'a>,
+    depth: usize,
+}
 
 pub struct ReportSettings {
     pub all: bool,
     pub reverse: bool,
     pub lines: usize,
     pub exclude: Vec<String>,
+    pub percent: bool,
+    pub sort: Vec<SortKey>,
+    pub filter: Filter,
+    pub collapse: Option<f64>,
+    pub format: Format,
+    pub template: Template,
+    pub units: Units,
+    pub tree: bool,
+    pub depth: usize,
+    pub children: Option<usize>,
+    pub width: Option<usize>,
+    pub color: bool,
+    pub theme: Color,
+    pub stream: Stream,
+}
+
+impl Default for ReportSettings {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ReportSettings {
@@ -24,12 +254,34 @@ impl ReportSettings {
             reverse: false,
             lines: 20,
             exclude: Vec::new(),
+            percent: false,
+            sort: vec![SortKey::Size],
+            filter: Filter::All,
+            collapse: None,
+            format: Format::Text,
+            template: Template::new(),
+            units: Units::new(),
+            tree: false,
+            depth: 3,
+            children: Some(5),
+            width: None,
+            color: false,
+            theme: Color::Yellow,
+            stream: Stream::Stdout,
         }
     }
 
     pub fn settings(&mut self, matches: &ArgMatches) {
         self.all = matches.occurrences_of("all") > 0;
         self.reverse = matches.occurrences_of("reverse") > 0;
+        self.percent = matches.occurrences_of("percent") > 0;
+        if matches.occurrences_of("files") > 0 {
+            self.filter = Filter::Files;
+        } else if matches.occurrences_of("dirs") > 0 {
+            self.filter = Filter::Dirs;
+        } else if matches.occurrences_of("leaves") > 0 {
+            self.filter = Filter::Leaves;
+        }
 
         if let Some(lines) = matches.value_of("lines") {
             self.lines = match lines.to_string().parse() {
@@ -44,44 +296,301 @@ impl ReportSettings {
         if let Some(exclude) = matches.values_of("exclude") {
             self.exclude = exclude.map(|x| x.to_string()).collect();
         }
+
+        if let Some(format) = matches.value_of("format") {
+            match Format::parse(format) {
+                Ok(format) => self.format = format,
+                Err(err) => eprintln!("Check format option: {}", err),
+            }
+        }
+
+        if matches.occurrences_of("collapse") > 0 {
+            let share = matches.value_of("collapse").unwrap_or("90");
+            match share.trim_end_matches('%').parse::<f64>() {
+                Ok(share) if (0.0..=100.0).contains(&share) => self.collapse = Some(share),
+                Ok(share) => eprintln!("Check collapse option: {} is not a percent", share),
+                Err(err) => eprintln!("Check collapse option: {}", err),
+            }
+        }
+
+        if let Some(sort) = matches.value_of("sort") {
+            match SortKey::parse(sort) {
+                Ok(sort) => self.sort = sort,
+                Err(err) => eprintln!("Check sort option: {}", err),
+            }
+        }
+
+        self.units.settings(matches);
+
+        self.tree = matches.occurrences_of("tree") > 0;
+        if let Some(depth) = matches.value_of("depth") {
+            match depth.parse() {
+                Ok(depth) => self.depth = depth,
+                Err(err) => eprintln!("Check depth option: {}", err),
+            }
+        }
+        if self.all {
+            self.children = None;
+        }
+        if let Some(children) = matches.value_of("children") {
+            match children.parse() {
+                Ok(children) => self.children = Some(children),
+                Err(err) => eprintln!("Check children option: {}", err),
+            }
+        }
+
+        if self.percent {
+            self.template = Template::parse(PERCENT_TEMPLATE).unwrap();
+        }
+        if let Some(template) = matches.value_of("output-format") {
+            match Template::parse(template) {
+                Ok(template) => self.template = template,
+                Err(err) => eprintln!("Check output-format option: {}", err),
+            }
+        }
+
+        if matches.occurrences_of("no-truncate") == 0 {
+            self.width = terminal::width(self.stream);
+        }
+        self.template.width = self.width;
+
+        // cygwin terminals show yellow poorly
+        if env::var_os("TERM").is_some_and(|term| term == "cygwin") {
+            self.theme = Color::Cyan;
+        }
+        let mut when = ColorWhen::Auto;
+        if let Some(color) = matches.value_of("color") {
+            match (ColorWhen::parse(color), parse_theme(color)) {
+                (Ok(color), _) => when = color,
+                (_, Ok(theme)) => self.theme = theme,
+                (Err(err), _) => eprintln!("Check color option: {}", err),
+            }
+        }
+        if let Some(theme) = matches.value_of("theme") {
+            match parse_theme(theme) {
+                Ok(theme) => self.theme = theme,
+                Err(err) => eprintln!("Check theme option: {}", err),
+            }
+        }
+        self.color = when.enabled(&|name| env::var(name).ok(), self.stream.is_terminal());
     }
 }
 
 /// Report
 ///
 /// Send report to stdout
-pub fn report(disk_space: BTreeMap<String, u64>, matches: &ArgMatches) {
-    report_stream(&mut io::stdout(), disk_space, matches)
+pub fn report(disk_space: BTreeMap<String, u64>, group: &DSGroup, matches: &ArgMatches) {
+    report_stream(
+        &mut io::stdout(),
+        Stream::Stdout,
+        disk_space,
+        group,
+        matches,
+    )
 }
 
 /// Report_Stream
 ///
-/// Sort the entries by size and output the top 20
+/// Print the capacity of the scanned filesystems, then sort the entries by
+/// size and output the top 20, each as a line of the template, or draw them
+/// as a tree with --tree.  Machine readable formats only hold the entries; a
+/// partial scan is noted on stderr.  Lines are fit and colored for the
+/// terminal on stream, the standard stream out writes to.
 #[allow(unused_must_use)]
 pub fn report_stream(
     out: &mut dyn io::Write,
-    mut disk_space: BTreeMap<String, u64>,
+    stream: Stream,
+    disk_space: BTreeMap<String, u64>,
+    group: &DSGroup,
     matches: &ArgMatches,
 ) {
     let mut rs = ReportSettings::new();
+    rs.stream = stream;
     rs.settings(matches);
-    if !rs.exclude.is_empty() {
-        disk_space = exclude(&rs, disk_space);
+    if rs.tree && rs.format == Format::Text {
+        if !group.filesystems.is_empty() {
+            filesystems(out, group, &rs.units, rs.width);
+            writeln!(out);
+        }
+        let entries = disk_space
+            .iter()
+            .filter(|(filename, _)| !excluded(&rs, filename))
+            .map(|(filename, size)| (filename.as_str(), *size));
+        let painted = Hierarchy::new(entries).write(
+            out,
+            rs.depth,
+            rs.children,
+            rs.width,
+            &rs.units,
+            &|size| color(size, &rs),
+        );
+        if let Err(err) = painted {
+            eprintln!("Error: {}", err);
+        }
+        if let Some(partial) = group.budget.exhausted {
+            partial_results(out, partial, group);
+        }
+        return;
+    }
+    let sorted = sort(&rs, &disk_space, group);
+    if rs.format != Format::Text {
+        let rows: Vec<Row> = sorted
+            .iter()
+            .map(|(filename, size)| Row::new(group, filename, *size))
+            .collect();
+        if let Err(err) = write_rows(out, rs.format, &rows) {
+            eprintln!("Error: {}", err);
+        }
+        if let Some(partial) = group.budget.exhausted {
+            partial_results(&mut io::stderr(), partial, group);
+        }
+        return;
     }
 
-    let mut unsorted = Vec::from_iter(disk_space);
-    let end = endpoint(&rs, unsorted.len());
+    if !group.filesystems.is_empty() {
+        filesystems(out, group, &rs.units, rs.width);
+        writeln!(out);
+    }
 
-    let sorted = if rs.reverse {
-        unsorted.sort_by(|&(_, a), &(_, b)| a.cmp(&b));
-        &unsorted[(unsorted.len() - end)..]
-    } else {
-        unsorted.sort_by(|&(_, a), &(_, b)| b.cmp(&a));
-        &unsorted[0..end]
-    };
+    let units = rs.units.fit(sorted.iter().map(|(_, size)| *size));
+    for (filename, size) in sorted {
+        let line = rs
+            .template
+            .render(group, &disk_space, &units, filename, size, &|size| {
+                color(size, &rs)
+            });
+        writeln!(out, "{}", line);
+    }
 
-    for &(ref filename, size) in sorted {
-        writeln!(out, "{} {}", color(size, matches), filename);
+    if let Some(partial) = group.budget.exhausted {
+        partial_results(out, partial, group);
+    }
+}
+
+/// Sort
+///
+/// Leave out the entries excluded, filtered or collapsed, order the rest by the keys
+/// of --sort, largest size first by default, and keep the number of lines
+/// requested.  Ties are broken by path.  -r lists the same entries last to
+/// first.
+fn sort<'a>(
+    rs: &ReportSettings,
+    disk_space: &'a BTreeMap<String, u64>,
+    group: &DSGroup,
+) -> Vec<(&'a str, u64)> {
+    let scanned = rs.sort.iter().any(SortKey::scanned);
+    let by_depth = rs.sort.contains(&SortKey::Depth);
+    let tree =
+        (rs.filter != Filter::All || rs.collapse.is_some()).then(|| Tree::new(group, disk_space));
+    let mut unsorted: Vec<Sortable> = disk_space
+        .iter()
+        .filter(|(filename, _)| !excluded(rs, filename))
+        .filter(|(filename, size)| {
+            tree.as_ref()
+                .is_none_or(|tree| tree.keeps(rs, filename, **size))
+        })
+        .map(|(filename, size)| Sortable {
+            row: match scanned {
+                true => Row::new(group, filename, *size),
+                false => Row {
+                    path: filename,
+                    size: *size,
+                    ..Row::default()
+                },
+            },
+            depth: match by_depth {
+                true => depth(group, Path::new(filename)),
+                false => 0,
+            },
+        })
+        .collect();
+    let end = endpoint(rs, unsorted.len());
+
+    unsorted.sort_by(|a, b| {
+        rs.sort
+            .iter()
+            .map(|key| key.compare(a, b))
+            .find(|order| order.is_ne())
+            .unwrap_or_else(|| a.row.path.cmp(b.row.path))
+    });
+    unsorted.truncate(end);
+    if rs.reverse {
+        unsorted.reverse();
+    }
+    unsorted
+        .into_iter()
+        .map(|entry| (entry.row.path, entry.row.size))
+        .collect()
+}
+
+/// Partial_Results
+///
+/// Explain why traversal stopped early and list the directories with
+/// entries that were not visited
+#[allow(unused_must_use)]
+fn partial_results(out: &mut dyn io::Write, partial: Partial, group: &DSGroup) {
+    writeln!(out);
+    writeln!(out, "Results are partial, {}.  Not fully visited:", partial);
+    for dir in &group.incomplete {
+        writeln!(out, "  {}", dir.display());
+    }
+}
+
+/// Filesystems
+///
+/// Print total, used, free and reserved space and inode usage for each
+/// anchor and mount point crossed, sizes in the units of the report.  On a
+/// terminal too narrow for them, the inode columns are left out and paths
+/// shortened.
+#[allow(unused_must_use)]
+fn filesystems(out: &mut dyn io::Write, group: &DSGroup, units: &Units, width: Option<usize>) {
+    let units = units.fit(
+        group
+            .filesystems
+            .values()
+            .flat_map(|info| [info.total, info.used, info.free, info.reserved]),
+    );
+    // four sizes and the type, then the three inode columns
+    let sizes = 4 * (units.width + 1) + 9;
+    let inodes = width.is_none_or(|width| width >= sizes + 28 + MIN_PATH);
+    let used = sizes + if inodes { 28 } else { 0 };
+    let room = width.map_or(usize::MAX, |width| width.saturating_sub(used).max(MIN_PATH));
+    let inode_header = match inodes {
+        true => format!("{:>10} {:>10} {:>5} ", "Inodes", "IUsed", "IUse%"),
+        false => String::new(),
+    };
+    writeln!(
+        out,
+        "{:>width$} {:>width$} {:>width$} {:>width$} {}{:<8} Filesystem",
+        "Total",
+        "Used",
+        "Free",
+        "Rsvd",
+        inode_header,
+        "Type",
+        width = units.width
+    );
+    for (path, info) in &group.filesystems {
+        let inode_columns = match inodes {
+            true => format!(
+                "{:>10} {:>10} {:>4.0}% ",
+                info.inodes,
+                info.inodes_used,
+                info.inodes_percent()
+            ),
+            false => String::new(),
+        };
+        writeln!(
+            out,
+            "{} {} {} {} {}{:<8} {}",
+            units.column(info.total),
+            units.column(info.used),
+            units.column(info.free),
+            units.column(info.reserved),
+            inode_columns,
+            group.mounts.filesystem_type(Path::new(path)).unwrap_or("-"),
+            shorten(path, "", room)
+        );
     }
 }
 
@@ -93,84 +602,52 @@ fn endpoint(rs: &ReportSettings, length: usize) -> usize {
     }
 }
 
-fn exclude(rs: &ReportSettings, disk_space: BTreeMap<String, u64>) -> BTreeMap<String, u64> {
-    let mut tmp = BTreeMap::new();
-    let mut include = true;
-    for filename in disk_space.keys() {
-        for exclusion in &rs.exclude {
-            if filename.contains(exclusion) {
-                include = false;
-                break;
-            }
-        }
-        if include {
-            tmp.insert(filename.to_string(), *disk_space.get(filename).unwrap());
-        } else {
-            include = true;
-        }
-    }
-    tmp
+/// Excluded
+///
+/// Whether the entry contains any STRING of --exclude
+fn excluded(rs: &ReportSettings, filename: &str) -> bool {
+    rs.exclude
+        .iter()
+        .any(|exclusion| filename.contains(exclusion.as_str()))
 }
 
 /// Color
 ///
-/// Returns a string that will contain colored unit output if the
-/// TERM environment variable is set.  Defaults to yellow on Linux and
-/// cyan on Windows(cygwin).  Color preference specified as a command
-/// line option.
-fn color(number: u64, matches: &ArgMatches) -> String {
-    match env::var_os("TERM") {
-        None => simple_units(number),
-        Some(term) => match term.as_os_str().to_str().unwrap() {
-            "cygwin" => simple_units(number).cyan().bold().to_string(),
-            _ => match matches.value_of("color") {
-                Some("black") => simple_units(number).black().bold().to_string(),
-                Some("red") => simple_units(number).red().bold().to_string(),
-                Some("green") => simple_units(number).green().bold().to_string(),
-                Some("yellow") => simple_units(number).yellow().bold().to_string(),
-                Some("blue") => simple_units(number).blue().bold().to_string(),
-                Some("magenta") => simple_units(number).magenta().bold().to_string(),
-                Some("cyan") => simple_units(number).cyan().bold().to_string(),
-                Some("white") => simple_units(number).white().bold().to_string(),
-                Some("none") => simple_units(number),
-                _ => simple_units(number).yellow().bold().to_string(),
-            },
-        },
+/// Returns the size column in bold in the color of the theme when coloring.
+/// The escapes are written here since colored only colors a terminal.
+fn color(size: &str, rs: &ReportSettings) -> String {
+    match rs.color {
+        true => format!("\x1b[1;{}m{}\x1b[0m", rs.theme.to_fg_str(), size),
+        false => size.to_string(),
     }
 }
 
 /// Simple_Units
 ///
-/// Convert number to human friendly format
-fn simple_units(number: u64) -> String {
-    let units = [" ", "K", "M", "G", "T", "P"];
-    let index: usize = (number as f64).log(1024.0).trunc() as usize;
-    let n = number / 1024u64.pow(index as u32);
-
-    if index == 0 || index > 5 {
-        format!("{:>6}", n)
-    } else {
-        format!("{:>5}{}", n, units[index])
-    }
+/// Convert number to human friendly format in the default units
+pub(crate) fn simple_units(number: u64) -> String {
+    Units::new().column(number)
 }
 
 #[cfg(test)]
 #[allow(unused_must_use)]
 mod tests {
     use super::*;
+    use crate::filesystem::FilesystemInfo;
+    use crate::mounts::parse;
     use clap::Arg;
-    use std::env;
+    use std::path::PathBuf;
 
     #[cfg(target_os = "linux")]
     #[test]
     fn report_short() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
 
         let mut out = Vec::new();
         let matches = App::new("DiskSpace").get_matches();
-        report_stream(&mut out, data, &matches);
+        report_stream(&mut out, Stream::Stdout, data, &DSGroup::new(), &matches);
         assert_eq!(
             out,
             format!(
@@ -186,15 +663,15 @@ mod tests {
     #[test]
     fn report_short_reverse() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
 
         let mut out = Vec::new();
         let args = vec!["ds", "-r"];
         let matches = App::new("DiskSpace")
             .arg(Arg::with_name("reverse").short("r"))
             .get_matches_from(args);
-        report_stream(&mut out, data, &matches);
+        report_stream(&mut out, Stream::Stdout, data, &DSGroup::new(), &matches);
         assert_eq!(
             out,
             format!(
@@ -210,8 +687,8 @@ mod tests {
     #[test]
     fn report_short_exclude() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
 
         let mut out = Vec::new();
         let args = vec!["ds", "-e", "fileB"];
@@ -223,7 +700,7 @@ mod tests {
                     .multiple(true),
             )
             .get_matches_from(args);
-        report_stream(&mut out, data, &matches);
+        report_stream(&mut out, Stream::Stdout, data, &DSGroup::new(), &matches);
         assert_eq!(
             out,
             format!("{} path/to/fileA\n", "    2K".yellow().bold(),).as_bytes()
@@ -235,38 +712,38 @@ mod tests {
     fn report_stdout() {
         let data = BTreeMap::new();
         let matches = App::new("DiskSpace").get_matches();
-        report(data, &matches);
+        report(data, &DSGroup::new(), &matches);
     }
 
     #[cfg(target_os = "linux")]
     #[test]
     fn report_long() {
         let mut data = BTreeMap::new();
-        data.insert("path/to/fileA".to_string(), 2048 as u64);
-        data.insert("path/to/fileB".to_string(), 1024 as u64);
-        data.insert("path/to/fileC".to_string(), 1023 as u64);
-        data.insert("path/to/fileD".to_string(), 1022 as u64);
-        data.insert("path/to/fileE".to_string(), 1021 as u64);
-        data.insert("path/to/fileF".to_string(), 1020 as u64);
-        data.insert("path/to/fileG".to_string(), 1019 as u64);
-        data.insert("path/to/fileH".to_string(), 1018 as u64);
-        data.insert("path/to/fileI".to_string(), 1017 as u64);
-        data.insert("path/to/fileJ".to_string(), 1016 as u64);
-        data.insert("path/to/fileK".to_string(), 1015 as u64);
-        data.insert("path/to/fileL".to_string(), 1014 as u64);
-        data.insert("path/to/fileM".to_string(), 1013 as u64);
-        data.insert("path/to/fileN".to_string(), 1012 as u64);
-        data.insert("path/to/fileO".to_string(), 1011 as u64);
-        data.insert("path/to/fileP".to_string(), 1010 as u64);
-        data.insert("path/to/fileQ".to_string(), 1009 as u64);
-        data.insert("path/to/fileR".to_string(), 1008 as u64);
-        data.insert("path/to/fileS".to_string(), 1007 as u64);
-        data.insert("path/to/fileT".to_string(), 1006 as u64);
-        data.insert("path/to/fileU".to_string(), 1005 as u64);
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+        data.insert("path/to/fileB".to_string(), 1024_u64);
+        data.insert("path/to/fileC".to_string(), 1023_u64);
+        data.insert("path/to/fileD".to_string(), 1022_u64);
+        data.insert("path/to/fileE".to_string(), 1021_u64);
+        data.insert("path/to/fileF".to_string(), 1020_u64);
+        data.insert("path/to/fileG".to_string(), 1019_u64);
+        data.insert("path/to/fileH".to_string(), 1018_u64);
+        data.insert("path/to/fileI".to_string(), 1017_u64);
+        data.insert("path/to/fileJ".to_string(), 1016_u64);
+        data.insert("path/to/fileK".to_string(), 1015_u64);
+        data.insert("path/to/fileL".to_string(), 1014_u64);
+        data.insert("path/to/fileM".to_string(), 1013_u64);
+        data.insert("path/to/fileN".to_string(), 1012_u64);
+        data.insert("path/to/fileO".to_string(), 1011_u64);
+        data.insert("path/to/fileP".to_string(), 1010_u64);
+        data.insert("path/to/fileQ".to_string(), 1009_u64);
+        data.insert("path/to/fileR".to_string(), 1008_u64);
+        data.insert("path/to/fileS".to_string(), 1007_u64);
+        data.insert("path/to/fileT".to_string(), 1006_u64);
+        data.insert("path/to/fileU".to_string(), 1005_u64);
 
         let mut out = Vec::new();
         let matches = App::new("DiskSpace").get_matches();
-        report_stream(&mut out, data, &matches);
+        report_stream(&mut out, Stream::Stdout, data, &DSGroup::new(), &matches);
         assert_eq!(
             out,
             format!(
@@ -316,6 +793,131 @@ mod tests {
         )
     }
 
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn report_filesystems() {
+        let mut data = BTreeMap::new();
+        data.insert("/mnt/fileA".to_string(), 512_u64);
+
+        let mut group = DSGroup::new();
+        group.filesystems.insert(
+            "/mnt".to_string(),
+            FilesystemInfo {
+                total: 4096,
+                used: 2048,
+                free: 1024,
+                reserved: 1024,
+                inodes: 100,
+                inodes_used: 25,
+            },
+        );
+
+        let mut out = Vec::new();
+        let args = vec!["ds", "-p"];
+        let matches = App::new("DiskSpace")
+            .arg(Arg::with_name("percent").short("p"))
+            .get_matches_from(args);
+        report_stream(&mut out, Stream::Stdout, data, &group, &matches);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                " Total   Used   Free   Rsvd     Inodes      IUsed IUse% Type     Filesystem
+    4K     2K     1K     1K        100         25   25% -        /mnt
+
+{}  25.0% /mnt/fileA
+",
+                "   512".yellow().bold()
+            )
+        )
+    }
+
+    #[test]
+    fn report_filesystems_narrow() {
+        let mut group = DSGroup::new();
+        group.filesystems.insert(
+            "/mnt/a/rather/deep/mount/point".to_string(),
+            FilesystemInfo {
+                total: 4096,
+                ..Default::default()
+            },
+        );
+
+        let mut out = Vec::new();
+        filesystems(&mut out, &group, &Units::new(), Some(70));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            " Total   Used   Free   Rsvd Type     Filesystem
+    4K      0      0      0 -        /mnt/a/rather/deep/mount/point
+"
+        );
+        let mut out = Vec::new();
+        filesystems(&mut out, &group, &Units::new(), Some(40));
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with(" -        /mnt/a/…/mount/point\n"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn report_mount_point() {
+        let mut data = BTreeMap::new();
+        data.insert("/home".to_string(), 2048_u64);
+
+        let mut group = DSGroup::new();
+        group.mounts.entries = parse("40 28 254:2 / /home rw - ext4 /dev/vda2 rw");
+        group.mounts.anchor(Path::new("/"));
+
+        let mut out = Vec::new();
+        let matches = App::new("DiskSpace").get_matches_from(vec!["ds"]);
+        report_stream(&mut out, Stream::Stdout, data, &group, &matches);
+        assert_eq!(
+            out,
+            format!("{} /home [ext4]\n", "    2K".yellow().bold()).as_bytes()
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn report_partial() {
+        let mut data = BTreeMap::new();
+        data.insert("path/to/fileA".to_string(), 2048_u64);
+
+        let mut group = DSGroup::new();
+        group.budget.exhausted = Some(Partial::TimeLimit);
+        group.incomplete.push(PathBuf::from("path/to"));
+        group.incomplete.push(PathBuf::from("path"));
+
+        let mut out = Vec::new();
+        let matches = App::new("DiskSpace").get_matches_from(vec!["ds"]);
+        report_stream(&mut out, Stream::Stdout, data, &group, &matches);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{} path/to/fileA
+
+Results are partial, time limit reached.  Not fully visited:
+  path/to
+  path
+",
+                "    2K".yellow().bold()
+            )
+        )
+    }
+
+    #[test]
+    fn percent_unknown_filesystem() {
+        let template = Template::parse(PERCENT_TEMPLATE).unwrap();
+        let line = template.render(
+            &DSGroup::new(),
+            &BTreeMap::new(),
+            &Units::new(),
+            "path/to/fileA",
+            512,
+            &|size| size.to_string(),
+        );
+        assert_eq!(line, "   512      - path/to/fileA");
+    }
+
     #[test]
     fn simple_units_bytes() {
         assert_eq!(simple_units(100), "   100");
@@ -328,7 +930,7 @@ mod tests {
 
     #[test]
     fn simple_units_kbytes_long() {
-        assert_eq!(simple_units(1025000), " 1000K");
+        assert_eq!(simple_units(1025000), " 1001K");
     }
 
     #[test]
@@ -347,10 +949,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".black().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;30m    10\x1b[0m");
     }
 
     #[test]
@@ -364,10 +968,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".red().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;31m    10\x1b[0m");
     }
 
     #[test]
@@ -381,10 +987,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".green().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;32m    10\x1b[0m");
     }
 
     #[test]
@@ -398,10 +1006,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".yellow().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;33m    10\x1b[0m");
     }
 
     #[test]
@@ -415,10 +1025,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".blue().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;34m    10\x1b[0m");
     }
 
     #[test]
@@ -432,10 +1044,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".magenta().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;35m    10\x1b[0m");
     }
 
     #[test]
@@ -449,10 +1063,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".cyan().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;36m    10\x1b[0m");
     }
 
     #[test]
@@ -466,10 +1082,12 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        rs.color = true;
 
-        let result = color(10, &matches);
-        assert_eq!(result, "    10".white().bold().to_string());
+        let result = color(&simple_units(10), &rs);
+        assert_eq!(result, "\x1b[1;37m    10\x1b[0m");
     }
 
     #[test]
@@ -483,21 +1101,62 @@ mod tests {
                     .takes_value(true),
             )
             .get_matches_from(args);
-        env::set_var("TERM", "xterm-256color");
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
 
-        let result = color(10, &matches);
+        let result = color(&simple_units(10), &rs);
+        assert!(!rs.color);
         assert_eq!(result, "    10");
     }
 
+    #[test]
+    fn color_theme() {
+        let args = vec!["ds", "--color=always", "--theme", "magenta"];
+        let matches = App::new("DiskSpace")
+            .arg(Arg::with_name("color").long("color").takes_value(true))
+            .arg(Arg::with_name("theme").long("theme").takes_value(true))
+            .get_matches_from(args);
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+
+        assert!(rs.color);
+        assert_eq!(rs.theme, Color::Magenta);
+        assert_eq!(color("2K", &rs), "\x1b[1;35m2K\x1b[0m");
+        assert!(parse_theme("purple").is_err());
+    }
+
+    #[test]
+    fn color_when() {
+        let var = |name: &'static str, value: &'static str| {
+            move |key: &str| (key == name).then(|| value.to_string())
+        };
+        let unset = |_: &str| None;
+        assert_eq!(ColorWhen::parse("never"), Ok(ColorWhen::Never));
+        assert_eq!(ColorWhen::parse("none"), Ok(ColorWhen::Never));
+        assert!(ColorWhen::parse("sometimes").is_err());
+
+        assert!(ColorWhen::Auto.enabled(&unset, true));
+        assert!(!ColorWhen::Auto.enabled(&unset, false));
+        assert!(ColorWhen::Always.enabled(&unset, false));
+        assert!(!ColorWhen::Never.enabled(&var("CLICOLOR_FORCE", "1"), true));
+        assert!(!ColorWhen::Auto.enabled(&var("NO_COLOR", "1"), true));
+        assert!(ColorWhen::Auto.enabled(&var("NO_COLOR", ""), true));
+        assert!(!ColorWhen::Auto.enabled(&var("CLICOLOR", "0"), true));
+        assert!(ColorWhen::Auto.enabled(&var("CLICOLOR_FORCE", "1"), false));
+        assert!(!ColorWhen::Auto.enabled(&var("CLICOLOR_FORCE", "0"), false));
+        assert!(ColorWhen::Always.enabled(&var("NO_COLOR", "1"), false));
+    }
+
     #[test]
     fn settings_defaults() {
         let args = vec!["ds"];
         let matches = App::new("DiskSpace").get_matches_from(args);
         let mut rs = ReportSettings::new();
         rs.settings(&matches);
-        assert_eq!(rs.all, false);
-        assert_eq!(rs.reverse, false);
+        assert!(!rs.all);
+        assert!(!rs.reverse);
         assert_eq!(rs.lines, 20);
+        assert!(!rs.percent);
     }
 
     #[test]
@@ -508,7 +1167,7 @@ mod tests {
             .get_matches_from(args);
         let mut rs = ReportSettings::new();
         rs.settings(&matches);
-        assert_eq!(rs.all, true);
+        assert!(rs.all);
     }
 
     #[test]
@@ -519,7 +1178,7 @@ mod tests {
             .get_matches_from(args);
         let mut rs = ReportSettings::new();
         rs.settings(&matches);
-        assert_eq!(rs.reverse, true);
+        assert!(rs.reverse);
     }
 
     #[test]
@@ -560,4 +1219,132 @@ mod tests {
         rs.settings(&matches);
         assert_eq!(rs.exclude, vec!["apple".to_string(), "pear".to_string()]);
     }
+
+    #[test]
+    fn settings_sort() {
+        let args = vec!["ds", "--sort", "count, name"];
+        let matches = App::new("DiskSpace")
+            .arg(Arg::with_name("sort").long("sort").takes_value(true))
+            .get_matches_from(args);
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        assert_eq!(rs.sort, vec![SortKey::Count, SortKey::Name]);
+        assert!(SortKey::parse("size,owner").is_err());
+    }
+
+    fn sorted(keys: &str, reverse: bool, lines: usize) -> Vec<String> {
+        let data = BTreeMap::from([
+            ("b/y".to_string(), 1024_u64),
+            ("b".to_string(), 2048_u64),
+            ("a/x".to_string(), 1024_u64),
+            ("a".to_string(), 1024_u64),
+            ("c".to_string(), 512_u64),
+        ]);
+        let mut rs = ReportSettings::new();
+        rs.sort = SortKey::parse(keys).unwrap();
+        rs.reverse = reverse;
+        rs.lines = lines;
+        sort(&rs, &data, &DSGroup::new())
+            .into_iter()
+            .map(|(filename, _)| filename.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sort_ties_by_path() {
+        assert_eq!(sorted("size", false, 20), ["b", "a", "a/x", "b/y", "c"]);
+        assert_eq!(sorted("size", false, 3), ["b", "a", "a/x"]);
+        assert_eq!(sorted("size", true, 3), ["a/x", "a", "b"]);
+    }
+
+    #[test]
+    fn sort_keys() {
+        assert_eq!(sorted("name", false, 20), ["a", "a/x", "b", "b/y", "c"]);
+        assert_eq!(
+            sorted("depth,size", false, 20),
+            ["b", "a", "c", "a/x", "b/y"]
+        );
+        assert_eq!(sorted("depth,name", true, 2), ["b", "a"]);
+    }
+
+    fn filtered(filter: Filter, group: &DSGroup) -> Vec<String> {
+        let data = BTreeMap::from([
+            ("./".to_string(), 4096_u64),
+            ("./a".to_string(), 3072_u64),
+            ("./a/b".to_string(), 2048_u64),
+            ("./a/b/f".to_string(), 2048_u64),
+            ("./a/g".to_string(), 1024_u64),
+            ("./h".to_string(), 1024_u64),
+        ]);
+        let mut rs = ReportSettings::new();
+        rs.filter = filter;
+        sort(&rs, &data, group)
+            .into_iter()
+            .map(|(filename, _)| filename.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn filter_imported() {
+        let group = DSGroup::new();
+        assert_eq!(filtered(Filter::Files, &group), ["./a/b/f", "./a/g", "./h"]);
+        assert_eq!(filtered(Filter::Dirs, &group), ["./", "./a", "./a/b"]);
+        assert_eq!(filtered(Filter::Leaves, &group), ["./a/b"]);
+    }
+
+    #[test]
+    fn filter_scanned() {
+        let mut group = DSGroup::new();
+        for dir in ["./", "./a", "./a/b", "./h"] {
+            group.dirs.insert(PathBuf::from(dir), vec![]);
+        }
+        assert_eq!(filtered(Filter::Files, &group), ["./a/b/f", "./a/g"]);
+        assert_eq!(filtered(Filter::Leaves, &group), ["./a/b", "./h"]);
+    }
+
+    #[test]
+    fn collapse() {
+        let mut rs = ReportSettings::new();
+        let collapsed = |rs: &ReportSettings| -> Vec<String> {
+            let data = BTreeMap::from([
+                ("t".to_string(), 1000_u64),
+                ("t/debug".to_string(), 950_u64),
+                ("t/debug/deps".to_string(), 900_u64),
+                ("t/debug/deps/a".to_string(), 500_u64),
+                ("t/debug/deps/b".to_string(), 400_u64),
+                ("t/debug/c".to_string(), 50_u64),
+                ("t/x".to_string(), 50_u64),
+            ]);
+            sort(rs, &data, &DSGroup::new())
+                .into_iter()
+                .map(|(filename, _)| filename.to_string())
+                .collect()
+        };
+        rs.collapse = Some(90.0);
+        assert_eq!(
+            collapsed(&rs),
+            [
+                "t/debug/deps",
+                "t/debug/deps/a",
+                "t/debug/deps/b",
+                "t/debug/c",
+                "t/x"
+            ]
+        );
+        rs.collapse = Some(95.0);
+        assert_eq!(collapsed(&rs)[..3], ["t", "t/debug", "t/debug/deps"]);
+
+        let matches = App::new("DiskSpace")
+            .arg(
+                Arg::with_name("collapse")
+                    .long("collapse")
+                    .min_values(0)
+                    .max_values(1)
+                    .require_equals(true),
+            )
+            .get_matches_from(vec!["ds", "--collapse"]);
+        let mut rs = ReportSettings::new();
+        rs.settings(&matches);
+        assert_eq!(rs.collapse, Some(90.0));
+    }
 }
@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+static REPORT: AtomicBool = AtomicBool::new(false);
+
+/// Exit status of a process killed by SIGINT
+pub const INTERRUPTED_STATUS: i32 = 130;
+
+/// install
+///
+/// The first SIGINT asks traversal to stop so the data scanned so far is
+/// reported.  A second SIGINT exits immediately.  SIGUSR1 asks for an
+/// intermediate report on stderr while scanning continues.  SIGINT does not
+/// restart system calls, so a scan blocked in one, such as a stat on a hung
+/// network mount, sees the first Ctrl-C when the call fails with EINTR.
+#[cfg(not(target_os = "windows"))]
+pub fn install() {
+    unsafe {
+        handle(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+            0,
+        );
+        handle(
+            libc::SIGUSR1,
+            on_report as *const () as libc::sighandler_t,
+            libc::SA_RESTART,
+        );
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn install() {
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as *const () as libc::sighandler_t,
+        );
+    }
+}
+
+/// restore
+///
+/// Return SIGINT to the default once scanning is done, so a single Ctrl-C
+/// stops the report.
+pub fn restore() {
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+unsafe fn handle(signal: libc::c_int, handler: libc::sighandler_t, flags: libc::c_int) {
+    let mut action: libc::sigaction = std::mem::zeroed();
+    action.sa_sigaction = handler;
+    action.sa_flags = flags;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(signal, &action, std::ptr::null_mut());
+}
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if INTERRUPTS.fetch_add(1, Ordering::SeqCst) > 0 {
+        unsafe { libc::_exit(INTERRUPTED_STATUS) };
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+extern "C" fn on_report(_signal: libc::c_int) {
+    request(&REPORT);
+}
+
+/// Raise a flag from a handler, for the next check to take
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+fn request(flag: &AtomicBool) {
+    flag.store(true, Ordering::SeqCst);
+}
+
+/// Lower a raised flag, returning whether it was raised
+fn take(flag: &AtomicBool) -> bool {
+    flag.load(Ordering::Relaxed) && flag.swap(false, Ordering::SeqCst)
+}
+
+/// interrupted
+///
+/// Check whether SIGINT has been received
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::Relaxed) > 0
+}
+
+/// report_requested
+///
+/// Check whether SIGUSR1 has been received since the last call
+pub fn report_requested() -> bool {
+    take(&REPORT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a flag of its own, as walks in other tests take REPORT
+    #[test]
+    fn report_flag() {
+        let flag = AtomicBool::new(false);
+        assert!(!take(&flag));
+        request(&flag);
+        request(&flag);
+        assert!(take(&flag));
+        assert!(!take(&flag));
+    }
+}
@@ -0,0 +1,644 @@
+use crate::ds::{civil_from_days, DSGroup};
+use crate::format::Row;
+use crate::terminal::{shorten, MIN_PATH};
+use crate::units::Units;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+#[cfg(not(target_os = "windows"))]
+use std::ffi::CStr;
+use std::path::{Component, Path};
+#[cfg(not(target_os = "windows"))]
+use std::{mem, ptr};
+
+/// The listing of ds, the size and path of each entry
+pub const DEFAULT_TEMPLATE: &str = "{size:h} {path}{mount}";
+
+/// The listing of ds -p
+pub const PERCENT_TEMPLATE: &str = "{size:h} {pct:>6} {path}{mount}";
+
+/// Template
+///
+/// A line of the text report from --output-format.  Placeholders in braces
+/// are replaced by the fields of each entry and {{ and }} are literal braces.
+/// A placeholder may be followed by a colon, an alignment of <, > or ^, a
+/// width and a conversion, as in {size:>8h} or {mtime:%F}.  Lines are fit
+/// to width when it is known by shortening paths.
+pub struct Template {
+    pieces: Vec<Piece>,
+    scanned: bool,
+    owners: RefCell<BTreeMap<u32, String>>,
+    pub width: Option<usize>,
+}
+
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+struct Field {
+    name: Name,
+    align: Option<char>,
+    width: usize,
+    conversion: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Name {
+    Size,
+    Apparent,
+    Allocated,
+    Exclusive,
+    Count,
+    Pct,
+    PctParent,
+    Owner,
+    Uid,
+    Mtime,
+    Btime,
+    Depth,
+    Type,
+    Path,
+    Mount,
+}
+
+const NAMES: [(&str, Name); 15] = [
+    ("size", Name::Size),
+    ("apparent", Name::Apparent),
+    ("allocated", Name::Allocated),
+    ("exclusive", Name::Exclusive),
+    ("count", Name::Count),
+    ("pct", Name::Pct),
+    ("pct_parent", Name::PctParent),
+    ("owner", Name::Owner),
+    ("uid", Name::Uid),
+    ("mtime", Name::Mtime),
+    ("btime", Name::Btime),
+    ("depth", Name::Depth),
+    ("type", Name::Type),
+    ("path", Name::Path),
+    ("mount", Name::Mount),
+];
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Template {
+    /// new
+    ///
+    /// The listing of ds
+    pub fn new() -> Template {
+        Template::parse(DEFAULT_TEMPLATE).unwrap()
+    }
+
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("{{{} is not closed", spec)),
+                        }
+                    }
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(Field::parse(&spec)?));
+                }
+                '}' => return Err("} is not opened, write }} for a brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        let scanned = pieces.iter().any(|piece| match piece {
+            Piece::Field(field) => !matches!(
+                field.name,
+                Name::Size | Name::Pct | Name::PctParent | Name::Depth | Name::Path | Name::Mount
+            ),
+            Piece::Text(_) => false,
+        });
+        Ok(Template {
+            pieces,
+            scanned,
+            owners: RefCell::new(BTreeMap::new()),
+            width: None,
+        })
+    }
+
+    /// render
+    ///
+    /// The line of path, reported with size.  The fields of the scan are only
+    /// looked up when used.  Percent of parent uses the sizes reported.  The
+    /// size is passed through paint after it is aligned, to color it.  With a
+    /// width, paths share the columns left by the other fields, and mount
+    /// types are left out when that is too narrow.
+    pub fn render(
+        &self,
+        group: &DSGroup,
+        sizes: &BTreeMap<String, u64>,
+        units: &Units,
+        path: &str,
+        size: u64,
+        paint: &dyn Fn(&str) -> String,
+    ) -> String {
+        let row = match self.scanned {
+            true => Row::new(group, path, size),
+            false => Row {
+                path,
+                size,
+                ..Row::default()
+            },
+        };
+        let mut values: Vec<(Option<Name>, String)> = self
+            .pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => (None, text.clone()),
+                Piece::Field(field) => (
+                    Some(field.name),
+                    align(&self.value(field, &row, group, sizes, units), field),
+                ),
+            })
+            .collect();
+        if let Some(width) = self.width {
+            fit(&mut values, &anchor(group, Path::new(path)), width);
+        }
+
+        let mut line = String::new();
+        for (name, value) in values {
+            if name == Some(Name::Size) {
+                line.push_str(&paint(&value));
+            } else {
+                line.push_str(&value);
+            }
+        }
+        line
+    }
+
+    fn value(
+        &self,
+        field: &Field,
+        row: &Row,
+        group: &DSGroup,
+        sizes: &BTreeMap<String, u64>,
+        units: &Units,
+    ) -> String {
+        let size = |size: Option<u64>| match size {
+            // aligned to the size column unless the field is aligned itself
+            Some(size) if field.conversion == "h" && field.width == 0 && field.align.is_none() => {
+                units.column(size)
+            }
+            Some(size) if field.conversion == "h" => units.format(size),
+            Some(size) => size.to_string(),
+            None => "-".to_string(),
+        };
+        let time = |time: Option<i64>| match time {
+            Some(time) if field.conversion.is_empty() => time.to_string(),
+            Some(time) => strftime(time, &field.conversion).unwrap_or_default(),
+            None => "-".to_string(),
+        };
+        let percent =
+            |percent: Option<f64>| percent.map_or("-".to_string(), |x| format!("{:.1}%", x));
+        let path = Path::new(row.path);
+        match field.name {
+            Name::Size => size(Some(row.size)),
+            Name::Apparent => size(Some(row.apparent)),
+            Name::Allocated => size(row.allocated),
+            Name::Exclusive => size(Some(row.exclusive)),
+            Name::Count => row.count.to_string(),
+            Name::Pct => percent(
+                group
+                    .filesystem(row.path)
+                    .map(|info| info.percent(row.size)),
+            ),
+            Name::PctParent => percent(
+                path.parent()
+                    .and_then(|parent| parent_size(sizes, parent))
+                    .filter(|parent| **parent > 0)
+                    .map(|parent| row.size as f64 * 100.0 / *parent as f64),
+            ),
+            Name::Owner => row.uid.map_or("-".to_string(), |uid| self.owner(uid)),
+            Name::Uid => row.uid.map_or("-".to_string(), |uid| uid.to_string()),
+            Name::Mtime => time(row.mtime),
+            Name::Btime => time(row.btime),
+            Name::Depth => depth(group, path).to_string(),
+            Name::Type => row.kind.to_string(),
+            Name::Path => row.path.to_string(),
+            Name::Mount => group
+                .mounts
+                .mount_point(path)
+                .map_or_else(String::new, |mount| format!(" [{}]", mount.fstype)),
+        }
+    }
+
+    /// owner
+    ///
+    /// The user name of uid, or the number for users without a name.  Names
+    /// are looked up once.
+    fn owner(&self, uid: u32) -> String {
+        self.owners
+            .borrow_mut()
+            .entry(uid)
+            .or_insert_with(|| user_name(uid).unwrap_or_else(|| uid.to_string()))
+            .clone()
+    }
+}
+
+impl Field {
+    /// parse
+    ///
+    /// Read name[:[align][width][conversion]].  Sizes convert with h to
+    /// human units and times with a strftime pattern.
+    fn parse(spec: &str) -> Result<Field, String> {
+        let (name, mut format) = spec.split_once(':').unwrap_or((spec, ""));
+        let (_, name) = NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(|| format!("{{{}}} is not a placeholder", name))?;
+        let align = match format.chars().next() {
+            Some(c @ ('<' | '>' | '^')) => {
+                format = &format[1..];
+                Some(c)
+            }
+            _ => None,
+        };
+        let digits = format
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(format.len());
+        let width = match digits {
+            0 => 0,
+            _ => format[..digits]
+                .parse()
+                .map_err(|_| format!("width of {{{}}} is too large", spec))?,
+        };
+        let conversion = &format[digits..];
+        let valid = match name {
+            Name::Size | Name::Apparent | Name::Allocated | Name::Exclusive => {
+                conversion.is_empty() || conversion == "h"
+            }
+            Name::Mtime | Name::Btime => strftime(0, conversion).is_ok(),
+            _ => conversion.is_empty(),
+        };
+        if !valid {
+            return Err(format!(
+                "{} is not a conversion of {{{}}}",
+                conversion, spec
+            ));
+        }
+        Ok(Field {
+            name: *name,
+            align,
+            width,
+            conversion: conversion.to_string(),
+        })
+    }
+}
+
+/// parent_size
+///
+/// The size reported for parent, which is keyed with a trailing slash when
+/// it is an anchor given as ./ or /tmp/
+fn parent_size<'a>(sizes: &'a BTreeMap<String, u64>, parent: &Path) -> Option<&'a u64> {
+    let parent = parent.to_string_lossy();
+    sizes
+        .get(parent.as_ref())
+        .or_else(|| sizes.get(&format!("{}/", parent)))
+}
+
+/// depth
+///
+/// Components of path below the anchor holding it, or all of them when no
+/// anchor does as for ds report
+pub(crate) fn depth(group: &DSGroup, path: &Path) -> usize {
+    let anchor = group
+        .anchors
+        .iter()
+        .filter(|anchor| path.starts_with(anchor))
+        .map(|anchor| anchor.components().count())
+        .max();
+    match anchor {
+        Some(anchor) => path.components().count() - anchor,
+        None => path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .count(),
+    }
+}
+
+/// anchor
+///
+/// The longest anchor holding path as it was given, empty for ds report
+fn anchor(group: &DSGroup, path: &Path) -> String {
+    group
+        .anchors
+        .iter()
+        .filter(|anchor| path.starts_with(anchor))
+        .max_by_key(|anchor| anchor.components().count())
+        .map_or_else(String::new, |anchor| anchor.to_string_lossy().to_string())
+}
+
+/// fit
+///
+/// Shorten the paths of a line to the width left by the other values,
+/// first leaving out mount types when the paths would be narrower than
+/// MIN_PATH
+fn fit(values: &mut [(Option<Name>, String)], anchor: &str, width: usize) {
+    let paths = values
+        .iter()
+        .filter(|(name, _)| *name == Some(Name::Path))
+        .count();
+    if paths == 0 {
+        return;
+    }
+    let room = |values: &[(Option<Name>, String)]| {
+        let others: usize = values
+            .iter()
+            .filter(|(name, _)| *name != Some(Name::Path))
+            .map(|(_, value)| value.chars().count())
+            .sum();
+        width.saturating_sub(others) / paths
+    };
+    if room(values) < MIN_PATH {
+        for (name, value) in values.iter_mut() {
+            if *name == Some(Name::Mount) {
+                value.clear();
+            }
+        }
+    }
+    let room = room(values);
+    for (name, value) in values.iter_mut() {
+        if *name == Some(Name::Path) {
+            *value = shorten(value, anchor, room);
+        }
+    }
+}
+
+/// align
+///
+/// Pad the value to the width of the field.  Numbers align right and text
+/// left unless the field says otherwise.
+fn align(value: &str, field: &Field) -> String {
+    let length = value.chars().count();
+    if length >= field.width {
+        return value.to_string();
+    }
+    let numeric = matches!(
+        field.name,
+        Name::Size
+            | Name::Apparent
+            | Name::Allocated
+            | Name::Exclusive
+            | Name::Count
+            | Name::Pct
+            | Name::PctParent
+            | Name::Uid
+            | Name::Depth
+    );
+    let fill = field.width - length;
+    match field.align {
+        Some('<') => format!("{}{}", value, " ".repeat(fill)),
+        Some('^') => format!(
+            "{}{}{}",
+            " ".repeat(fill / 2),
+            value,
+            " ".repeat(fill - fill / 2)
+        ),
+        Some(_) => format!("{}{}", " ".repeat(fill), value),
+        None if numeric => format!("{}{}", " ".repeat(fill), value),
+        None => format!("{}{}", value, " ".repeat(fill)),
+    }
+}
+
+/// strftime
+///
+/// Format seconds since the epoch in UTC with %Y, %m, %d, %H, %M, %S, %F,
+/// %T, %s and %%
+pub fn strftime(time: i64, pattern: &str) -> Result<String, String> {
+    let (year, month, day) = civil_from_days(time.div_euclid(86400));
+    let seconds = time.rem_euclid(86400);
+    let (hour, minute, second) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&format!("{:04}", year)),
+            Some('m') => out.push_str(&format!("{:02}", month)),
+            Some('d') => out.push_str(&format!("{:02}", day)),
+            Some('H') => out.push_str(&format!("{:02}", hour)),
+            Some('M') => out.push_str(&format!("{:02}", minute)),
+            Some('S') => out.push_str(&format!("{:02}", second)),
+            Some('F') => out.push_str(&format!("{:04}-{:02}-{:02}", year, month, day)),
+            Some('T') => out.push_str(&format!("{:02}:{:02}:{:02}", hour, minute, second)),
+            Some('s') => out.push_str(&time.to_string()),
+            Some('%') => out.push('%'),
+            Some(c) => return Err(format!("%{} is not supported", c)),
+            None => return Err("% ends the pattern".to_string()),
+        }
+    }
+    Ok(out)
+}
+
+/// user_name
+///
+/// Look up the name of uid in the password database
+#[cfg(not(target_os = "windows"))]
+fn user_name(uid: u32) -> Option<String> {
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    let mut result = ptr::null_mut();
+    loop {
+        let err =
+            unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+        if err == libc::ERANGE && buf.len() < 1 << 20 {
+            buf.resize(buf.len() * 2, 0);
+            continue;
+        }
+        if err != 0 || result.is_null() {
+            return None;
+        }
+        let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+        return Some(name.to_string_lossy().to_string());
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn user_name(_uid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filesystem::FilesystemInfo;
+    use crate::mounts::parse;
+    use crate::walk::{Kind, Meta};
+    use std::path::PathBuf;
+
+    fn group() -> DSGroup {
+        let mut group = DSGroup::new();
+        let data = PathBuf::from("/srv/data");
+        group.anchors.push(PathBuf::from("/srv"));
+        group.dirs.insert(data.clone(), vec![]);
+        group.metas.insert(
+            data,
+            Meta {
+                kind: Kind::Directory,
+                dev: 1,
+                ino: 2,
+                nlink: 2,
+                uid: 0,
+                size: 4096,
+                blocks: 8,
+                mtime: 1709209815,
+                btime: None,
+                mnt_id: None,
+            },
+        );
+        group.counts.insert("/srv/data".to_string(), 3);
+        group.apparent.insert("/srv/data".to_string(), 6144);
+        group.allocated.insert("/srv/data".to_string(), 8192);
+        group.exclusive.insert("/srv/data".to_string(), 1024);
+        group.filesystems.insert(
+            "/srv".to_string(),
+            FilesystemInfo {
+                total: 16384,
+                used: 8192,
+                free: 8192,
+                reserved: 0,
+                inodes: 100,
+                inodes_used: 25,
+            },
+        );
+        group.mounts.entries = parse("40 28 254:2 / /srv/data rw - ext4 /dev/vda2 rw");
+        group.mounts.anchor(Path::new("/"));
+        group
+    }
+
+    fn render(template: &str) -> String {
+        let sizes = BTreeMap::from([("/srv".to_string(), 4096), ("/srv/data".to_string(), 2048)]);
+        Template::parse(template).unwrap().render(
+            &group(),
+            &sizes,
+            &Units::new(),
+            "/srv/data",
+            2048,
+            &|size| format!("<{}>", size),
+        )
+    }
+
+    #[test]
+    fn default_listing() {
+        assert_eq!(render(DEFAULT_TEMPLATE), "<    2K> /srv/data [ext4]");
+        assert_eq!(render(PERCENT_TEMPLATE), "<    2K>  25.0% /srv/data [ext4]");
+    }
+
+    #[test]
+    fn narrow() {
+        let sizes = BTreeMap::new();
+        let mut template = Template::new();
+        let render = |template: &Template| {
+            template.render(
+                &group(),
+                &sizes,
+                &Units::new(),
+                "/srv/data",
+                2048,
+                &|size| size.to_string(),
+            )
+        };
+        template.width = Some(40);
+        assert_eq!(render(&template), "    2K /srv/data [ext4]");
+        template.width = Some(20);
+        assert_eq!(render(&template), "    2K /srv/data");
+        template.width = Some(12);
+        assert_eq!(render(&template), "    2K /s…ta");
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            render("{size} {apparent:h} {allocated} {exclusive} {count} {depth} {type}"),
+            "<2048>     6K 8192 1024 3 1 directory"
+        );
+        assert_eq!(
+            render("{mtime} {mtime:%F %T} {btime:%F} {pct_parent} {uid}"),
+            "1709209815 2024-02-29 12:30:15 - 50.0% 0"
+        );
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(render("{owner}"), "root");
+    }
+
+    #[test]
+    fn parent_anchor() {
+        let sizes = BTreeMap::from([("./".to_string(), 400), ("/tmp/".to_string(), 100)]);
+        assert_eq!(parent_size(&sizes, Path::new(".")), Some(&400));
+        assert_eq!(parent_size(&sizes, Path::new("/tmp")), Some(&100));
+        assert_eq!(parent_size(&sizes, Path::new("/")), None);
+    }
+
+    #[test]
+    fn unscanned() {
+        let template = Template::parse("{path} {type} {count} {depth} {allocated}").unwrap();
+        let line = template.render(
+            &DSGroup::new(),
+            &BTreeMap::new(),
+            &Units::new(),
+            "srv/a",
+            10,
+            &|size| size.to_string(),
+        );
+        assert_eq!(line, "srv/a file 1 2 -");
+    }
+
+    #[test]
+    fn alignment() {
+        assert_eq!(
+            render("[{count:4}|{type:<4}|{depth:<3}]"),
+            "[   3|directory|1  ]"
+        );
+        assert_eq!(render("[{pct:^9}|{path:>12}]"), "[  25.0%  |   /srv/data]");
+        assert_eq!(render("{{{count}}}"), "{3}");
+        assert_eq!(render("[{size:<8h}]"), "[<2K      >]");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Template::parse("{name}").is_err());
+        assert!(Template::parse("{size:x}").is_err());
+        assert!(Template::parse("{count:h}").is_err());
+        assert!(Template::parse("{mtime:%Q}").is_err());
+        assert!(Template::parse("{size").is_err());
+        assert!(Template::parse("size}").is_err());
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(strftime(0, "%F %T"), Ok("1970-01-01 00:00:00".to_string()));
+        assert_eq!(
+            strftime(-1, "%Y%m%d %H%M%S %s %%"),
+            Ok("19691231 235959 -1 %".to_string())
+        );
+    }
+}
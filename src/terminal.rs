@@ -0,0 +1,135 @@
+use std::env;
+use std::io;
+use std::io::IsTerminal;
+#[cfg(not(target_os = "windows"))]
+use std::mem;
+
+/// Narrowest path shown before extra columns are dropped to make room
+pub const MIN_PATH: usize = 20;
+
+/// Columns of a terminal whose size is not known
+const DEFAULT_WIDTH: usize = 80;
+
+/// Stream
+///
+/// The standard stream a report is written to.  The final report goes to
+/// stdout and the intermediate report of SIGUSR1 to stderr.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    pub fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn fd(self) -> libc::c_int {
+        match self {
+            Stream::Stdout => libc::STDOUT_FILENO,
+            Stream::Stderr => libc::STDERR_FILENO,
+        }
+    }
+}
+
+/// Width
+///
+/// The columns of the terminal on stream, from the terminal itself, then
+/// COLUMNS, then 80.  None when stream is not a terminal, so redirected
+/// output is never shortened.
+pub fn width(stream: Stream) -> Option<usize> {
+    if !stream.is_terminal() {
+        return None;
+    }
+    let columns = env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0);
+    Some(window(stream).or(columns).unwrap_or(DEFAULT_WIDTH))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn window(stream: Stream) -> Option<usize> {
+    unsafe {
+        let mut size: libc::winsize = mem::zeroed();
+        match libc::ioctl(stream.fd(), libc::TIOCGWINSZ, &mut size) {
+            0 if size.ws_col > 0 => Some(usize::from(size.ws_col)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn window(_stream: Stream) -> Option<usize> {
+    None
+}
+
+/// Shorten
+///
+/// Fit path in room characters by replacing its middle with an ellipsis,
+/// keeping the prefix, such as the anchor, and the file name whole when
+/// they fit.  The rest of room shows the start and end of the directories
+/// between them.
+pub fn shorten(path: &str, prefix: &str, room: usize) -> String {
+    let chars: Vec<char> = path.chars().collect();
+    if chars.len() <= room {
+        return path.to_string();
+    }
+    let keep = room.saturating_sub(1);
+    let prefix = match path.starts_with(prefix) {
+        true => prefix.chars().count(),
+        false => 0,
+    };
+    let name = match path.trim_end_matches('/').rfind('/') {
+        Some(slash) => chars.len() - path[..slash].chars().count(),
+        None => chars.len(),
+    };
+    let (head, tail) = if prefix + name <= keep {
+        let middle = keep - prefix - name;
+        (prefix + middle.div_ceil(2), name + middle / 2)
+    } else {
+        (keep / 2, keep - keep / 2)
+    };
+    let mut short: String = chars[..head].iter().collect();
+    short.push('…');
+    short.extend(&chars[chars.len() - tail..]);
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits() {
+        assert_eq!(shorten("/usr/lib", "/usr", 8), "/usr/lib");
+        assert_eq!(shorten("", "", 0), "");
+    }
+
+    #[test]
+    fn middle() {
+        let path = "/home/user/projects/diskspace/target/debug/build/output.log";
+        let short = shorten(path, "/home/user", 40);
+        assert_eq!(short, "/home/user/projects…bug/build/output.log");
+        assert!(short.chars().count() <= 40);
+        assert_eq!(
+            shorten(path, "/home/user", 26),
+            "/home/user/p…ld/output.log"
+        );
+        assert_eq!(shorten(path, "/home/user", 22), "/home/user…/output.log");
+        assert_eq!(shorten(path, "/home/user", 21), "/home/user…output.log");
+        assert_eq!(shorten(path, "/srv", 3), "/…g");
+        assert_eq!(shorten(path, "/srv", 0), "…");
+    }
+
+    #[test]
+    fn characters() {
+        assert_eq!(shorten("./ünïcödé/ñame", "./", 9), "./ü…/ñame");
+        assert_eq!(shorten("./directory/", "./", 8), "./d…ory/");
+    }
+}
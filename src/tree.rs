@@ -0,0 +1,283 @@
+use crate::terminal::{shorten, MIN_PATH};
+use crate::units::Units;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+/// Width of the bar in characters, each split in eighths
+const BAR_WIDTH: usize = 10;
+const EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+/// Hierarchy
+///
+/// The entries of the report under their parents.  Roots are the entries
+/// whose parent is not listed, such as the anchors.  Children are ordered
+/// largest first, then by path.
+pub struct Hierarchy<'a> {
+    roots: Vec<(&'a str, u64)>,
+    children: BTreeMap<&'a Path, Vec<(&'a str, u64)>>,
+}
+
+/// Line
+///
+/// A node of the tree as written: its size, share of the parent, the guides
+/// before it and its name
+struct Line {
+    size: u64,
+    percent: f64,
+    guides: String,
+    name: String,
+}
+
+impl<'a> Hierarchy<'a> {
+    pub fn new(entries: impl Iterator<Item = (&'a str, u64)>) -> Hierarchy<'a> {
+        let entries: BTreeMap<&Path, (&str, u64)> = entries
+            .map(|(filename, size)| (Path::new(filename), (filename, size)))
+            .collect();
+        let mut roots = Vec::new();
+        let mut children: BTreeMap<&Path, Vec<(&str, u64)>> = BTreeMap::new();
+        for (path, entry) in &entries {
+            match path.parent().filter(|parent| entries.contains_key(parent)) {
+                Some(parent) => children.entry(parent).or_default().push(*entry),
+                None => roots.push(*entry),
+            }
+        }
+        let largest = |a: &(&str, u64), b: &(&str, u64)| b.1.cmp(&a.1).then(a.0.cmp(b.0));
+        roots.sort_by(largest);
+        for siblings in children.values_mut() {
+            siblings.sort_by(largest);
+        }
+        Hierarchy { roots, children }
+    }
+
+    /// write
+    ///
+    /// Draw each root and the entries below it to depth levels, at most
+    /// children of each, with the rest summed on one line.  Sizes are in
+    /// the units of the report, painted by paint.  Names are shortened to
+    /// fit width, and bars left out when the deepest names would be
+    /// narrower than MIN_PATH.
+    pub fn write(
+        &self,
+        out: &mut dyn io::Write,
+        depth: usize,
+        children: Option<usize>,
+        width: Option<usize>,
+        units: &Units,
+        paint: &dyn Fn(&str) -> String,
+    ) -> io::Result<()> {
+        let mut lines = Vec::new();
+        let total: u64 = self.roots.iter().map(|(_, size)| size).sum();
+        for (filename, size) in &self.roots {
+            lines.push(Line {
+                size: *size,
+                percent: percent(*size, total),
+                guides: String::new(),
+                name: filename.to_string(),
+            });
+            self.branch(&mut lines, filename, *size, "", depth, children);
+        }
+
+        let units = units.fit(lines.iter().map(|line| line.size));
+        // the size, percent and bar columns before the guides
+        let columns = units.width + 8;
+        let guides = lines
+            .iter()
+            .map(|line| line.guides.chars().count())
+            .max()
+            .unwrap_or(0);
+        let bars = width.is_none_or(|width| width >= columns + BAR_WIDTH + 1 + guides + MIN_PATH);
+        for line in lines {
+            let bar = match bars {
+                true => format!("{} ", bar(line.percent)),
+                false => String::new(),
+            };
+            let name = match width {
+                Some(width) => {
+                    let used = columns + bar.chars().count() + line.guides.chars().count();
+                    shorten(&line.name, "", width.saturating_sub(used))
+                }
+                None => line.name,
+            };
+            writeln!(
+                out,
+                "{} {:>5.1}% {}{}{}",
+                paint(&units.column(line.size)),
+                line.percent,
+                bar,
+                line.guides,
+                name
+            )?;
+        }
+        Ok(())
+    }
+
+    /// branch
+    ///
+    /// Add the children of filename below it, each guide extending the
+    /// indent of its parent
+    fn branch(
+        &self,
+        lines: &mut Vec<Line>,
+        filename: &str,
+        size: u64,
+        indent: &str,
+        depth: usize,
+        children: Option<usize>,
+    ) {
+        let siblings = match self.children.get(Path::new(filename)) {
+            Some(siblings) if depth > 0 => siblings,
+            _ => return,
+        };
+        let shown = children.map_or(siblings.len(), |limit| limit.min(siblings.len()));
+        let hidden = &siblings[shown..];
+        for (index, (child, child_size)) in siblings[..shown].iter().enumerate() {
+            let last = index + 1 == shown && hidden.is_empty();
+            let (guide, extension) = match last {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            lines.push(Line {
+                size: *child_size,
+                percent: percent(*child_size, size),
+                guides: format!("{}{}", indent, guide),
+                name: name(child),
+            });
+            let indent = format!("{}{}", indent, extension);
+            self.branch(lines, child, *child_size, &indent, depth - 1, children);
+        }
+        if !hidden.is_empty() {
+            let rest: u64 = hidden.iter().map(|(_, size)| size).sum();
+            lines.push(Line {
+                size: rest,
+                percent: percent(rest, size),
+                guides: format!("{}└── ", indent),
+                name: format!("({} more)", hidden.len()),
+            });
+        }
+    }
+}
+
+/// The share of whole, none of nothing
+fn percent(part: u64, whole: u64) -> f64 {
+    match whole {
+        0 => 0.0,
+        whole => part as f64 * 100.0 / whole as f64,
+    }
+}
+
+/// The last component of the path, or the path itself for roots such as /
+fn name(filename: &str) -> String {
+    match Path::new(filename).file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => filename.to_string(),
+    }
+}
+
+/// Bar
+///
+/// Blocks filling the share of the bar width to the nearest eighth, padded
+/// with spaces
+fn bar(percent: f64) -> String {
+    let eighths = (percent.clamp(0.0, 100.0) * (BAR_WIDTH * 8) as f64 / 100.0).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    bar.push_str(EIGHTHS[eighths % 8]);
+    let length = bar.chars().count();
+    bar.push_str(&" ".repeat(BAR_WIDTH - length));
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(entries: &[(&str, u64)], depth: usize, children: Option<usize>) -> String {
+        draw_width(entries, depth, children, None)
+    }
+
+    fn draw_width(
+        entries: &[(&str, u64)],
+        depth: usize,
+        children: Option<usize>,
+        width: Option<usize>,
+    ) -> String {
+        let mut out = Vec::new();
+        Hierarchy::new(entries.iter().copied())
+            .write(&mut out, depth, children, width, &Units::new(), &|size| {
+                size.to_string()
+            })
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn bars() {
+        assert_eq!(bar(100.0), "██████████");
+        assert_eq!(bar(0.0), "          ");
+        assert_eq!(bar(50.0), "█████     ");
+        assert_eq!(bar(31.25), "███▏      ");
+    }
+
+    #[test]
+    fn guides() {
+        let entries = [
+            ("./", 4096),
+            ("./a", 3072),
+            ("./a/b", 1024),
+            ("./a/c", 2048),
+            ("./d", 1024),
+        ];
+        assert_eq!(
+            draw(&entries, 3, None),
+            "    4K 100.0% ██████████ ./\n\
+             \x20   3K  75.0% ███████▌   ├── a\n\
+             \x20   2K  66.7% ██████▋    │   ├── c\n\
+             \x20   1K  33.3% ███▍       │   └── b\n\
+             \x20   1K  25.0% ██▌        └── d\n"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let entries = [
+            ("/srv", 600),
+            ("/srv/a", 300),
+            ("/srv/a/b", 300),
+            ("/srv/c", 200),
+            ("/srv/d", 100),
+        ];
+        assert_eq!(
+            draw(&entries, 1, Some(1)),
+            "   600 100.0% ██████████ /srv\n\
+             \x20  300  50.0% █████      ├── a\n\
+             \x20  300  50.0% █████      └── (2 more)\n"
+        );
+        assert_eq!(draw(&entries, 0, None), "   600 100.0% ██████████ /srv\n");
+    }
+
+    #[test]
+    fn roots() {
+        let entries = [("/tmp", 100), ("/var", 300), ("/var/log", 0)];
+        assert_eq!(
+            draw(&entries, 3, None),
+            "   300  75.0% ███████▌   /var\n\
+             \x20    0   0.0%            └── log\n\
+             \x20  100  25.0% ██▌        /tmp\n"
+        );
+    }
+
+    #[test]
+    fn narrow() {
+        let entries = [("/srv", 600), ("/srv/a_rather_long_directory_name", 300)];
+        assert_eq!(
+            draw_width(&entries, 3, None, Some(60)),
+            "   600 100.0% ██████████ /srv\n\
+             \x20  300  50.0% █████      └── a_rather_long_directory_name\n"
+        );
+        assert_eq!(
+            draw_width(&entries, 3, None, Some(40)),
+            "   600 100.0% /srv\n\
+             \x20  300  50.0% └── a_rather_l…ectory_name\n"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use clap::ArgMatches;
+
+/// Letters of the powers of 1024, and of 1000 with --si
+const UNITS: [&str; 7] = ["", "K", "M", "G", "T", "P", "E"];
+const SI_UNITS: [&str; 7] = ["", "k", "M", "G", "T", "P", "E"];
+
+/// Units
+///
+/// How sizes are written in the text report.  Human units are powers of 1024,
+/// or of 1000 with --si, rounded to precision decimals.  A block size writes
+/// the number of blocks rounded up as du does, with the unit letter when the
+/// size was given as one.  Bytes writes exact values.  Width is that of the
+/// size column.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Units {
+    pub si: bool,
+    pub precision: usize,
+    pub block_size: Option<BlockSize>,
+    pub bytes: bool,
+    pub separators: bool,
+    pub width: usize,
+}
+
+/// BlockSize
+///
+/// The size of -B and the suffix written after each count
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSize {
+    pub size: u64,
+    pub suffix: String,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Units {
+    pub fn new() -> Units {
+        Units {
+            si: false,
+            precision: 0,
+            block_size: None,
+            bytes: false,
+            separators: false,
+            width: 6,
+        }
+    }
+
+    pub fn settings(&mut self, matches: &ArgMatches) {
+        self.si = matches.occurrences_of("si") > 0;
+        self.bytes = matches.occurrences_of("bytes") > 0;
+        self.separators = matches.occurrences_of("thousands") > 0;
+
+        if let Some(precision) = matches.value_of("precision") {
+            match precision.parse::<usize>() {
+                Ok(precision) if precision <= 9 => self.precision = precision,
+                Ok(precision) => eprintln!("Check precision option: {} is more than 9", precision),
+                Err(err) => eprintln!("Check precision option: {}", err),
+            }
+        }
+
+        if let Some(block_size) = matches.value_of("block-size") {
+            match parse_block_size(block_size) {
+                Ok((block_size, separators)) => {
+                    self.block_size = Some(block_size);
+                    self.separators |= separators;
+                }
+                Err(err) => eprintln!("Check block-size option: {}", err),
+            }
+        }
+
+        // a digit more for each decimal and the point
+        if self.precision > 0 && !self.exact() {
+            self.width += self.precision + 1;
+        }
+    }
+
+    /// exact
+    ///
+    /// Whether sizes are whole numbers of bytes or blocks rather than human
+    /// units, so their width depends on the values
+    fn exact(&self) -> bool {
+        self.bytes || self.block_size.is_some()
+    }
+
+    /// fit
+    ///
+    /// The units with the column wide enough for each of the sizes.  Human
+    /// units keep their width.
+    pub fn fit(&self, sizes: impl Iterator<Item = u64>) -> Units {
+        let mut units = self.clone();
+        if self.exact() {
+            for size in sizes {
+                units.width = units.width.max(self.format(size).chars().count());
+            }
+        }
+        units
+    }
+
+    /// column
+    ///
+    /// The size right aligned to the width of the column
+    pub fn column(&self, size: u64) -> String {
+        format!("{:>width$}", self.format(size), width = self.width)
+    }
+
+    /// format
+    ///
+    /// The size in these units, not padded
+    pub fn format(&self, size: u64) -> String {
+        if self.bytes {
+            return self.group(size);
+        }
+        if let Some(block_size) = &self.block_size {
+            let blocks = size.div_ceil(block_size.size);
+            return format!("{}{}", self.group(blocks), block_size.suffix);
+        }
+        self.human(size)
+    }
+
+    /// human
+    ///
+    /// Bytes below the first unit, otherwise the largest unit the size
+    /// reaches rounded half up to precision decimals.  Rounding up to a
+    /// whole next unit moves to it, so 1023.6K is 1M.
+    fn human(&self, size: u64) -> String {
+        let base: u128 = if self.si { 1000 } else { 1024 };
+        let letters = if self.si { SI_UNITS } else { UNITS };
+        let size = u128::from(size);
+        if size < base {
+            return size.to_string();
+        }
+        let mut index = 0;
+        while index + 1 < letters.len() && size >= base.pow(index as u32 + 1) {
+            index += 1;
+        }
+        let scale = 10u128.pow(self.precision as u32);
+        let rounded = |index: usize| {
+            let divisor = base.pow(index as u32);
+            (size * scale * 2 + divisor) / (divisor * 2)
+        };
+        let mut scaled = rounded(index);
+        if scaled >= base * scale && index + 1 < letters.len() {
+            index += 1;
+            scaled = rounded(index);
+        }
+        if self.precision == 0 {
+            format!("{}{}", scaled, letters[index])
+        } else {
+            format!(
+                "{}.{:0precision$}{}",
+                scaled / scale,
+                scaled % scale,
+                letters[index],
+                precision = self.precision
+            )
+        }
+    }
+
+    /// group
+    ///
+    /// Digits of number with commas between thousands when asked for
+    fn group(&self, number: u64) -> String {
+        let digits = number.to_string();
+        if !self.separators {
+            return digits;
+        }
+        let mut grouped = String::new();
+        for (index, digit) in digits.chars().enumerate() {
+            if index > 0 && (digits.len() - index).is_multiple_of(3) {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+}
+
+/// parse_block_size
+///
+/// Read a block size as du -B does: an optional ' for thousands separators,
+/// a number and a unit of K, M, G, T, P or E for powers of 1024, KB, MB and
+/// so on for powers of 1000, or KiB, MiB and so on.  A unit without a number
+/// is also written after each size.
+pub fn parse_block_size(value: &str) -> Result<(BlockSize, bool), String> {
+    let invalid = || format!("invalid block size {}", value);
+    let (separators, rest) = match value.strip_prefix('\'') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    let digits = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    let (number, unit) = rest.split_at(digits);
+    let number: u64 = match number {
+        "" => 1,
+        number => number.parse().map_err(|_| invalid())?,
+    };
+    let (letter, base) = match unit.char_indices().nth(1) {
+        None => (unit, 1024),
+        Some((split, _)) => match &unit[split..] {
+            "B" => (&unit[..split], 1000),
+            "iB" => (&unit[..split], 1024),
+            _ => return Err(invalid()),
+        },
+    };
+    let power = match letter.to_ascii_uppercase().as_str() {
+        "" => 0,
+        letter => UNITS
+            .iter()
+            .position(|unit| *unit == letter)
+            .ok_or_else(invalid)?,
+    };
+    let size = (base as u64)
+        .checked_pow(power as u32)
+        .and_then(|unit| unit.checked_mul(number))
+        .filter(|size| *size > 0)
+        .ok_or_else(invalid)?;
+    let suffix = match digits {
+        0 => unit.to_string(),
+        _ => String::new(),
+    };
+    Ok((BlockSize { size, suffix }, separators))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn human(si: bool, precision: usize, size: u64) -> String {
+        let mut units = Units::new();
+        units.si = si;
+        units.precision = precision;
+        units.format(size)
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(human(false, 0, 1023), "1023");
+        assert_eq!(human(false, 0, 1024), "1K");
+        assert_eq!(human(false, 0, 1535), "1K");
+        assert_eq!(human(false, 0, 1536), "2K");
+        assert_eq!(human(false, 0, 1_048_063), "1023K");
+        assert_eq!(human(false, 0, 1_048_064), "1M");
+        assert_eq!(human(false, 2, 2_136_746_229), "1.99G");
+        assert_eq!(human(false, 1, 1_048_064), "1023.5K");
+        assert_eq!(human(false, 1, 1_048_525), "1.0M");
+    }
+
+    #[test]
+    fn large() {
+        assert_eq!(human(false, 0, 1 << 60), "1E");
+        assert_eq!(human(false, 1, u64::MAX), "16.0E");
+        assert_eq!(human(true, 0, u64::MAX), "18E");
+    }
+
+    #[test]
+    fn si() {
+        assert_eq!(human(true, 0, 999), "999");
+        assert_eq!(human(true, 0, 1000), "1k");
+        assert_eq!(human(true, 1, 1_250_000), "1.3M");
+        assert_eq!(human(true, 0, 999_500), "1M");
+    }
+
+    #[test]
+    fn exact() {
+        let mut units = Units::new();
+        units.bytes = true;
+        units.separators = true;
+        assert_eq!(units.format(1_234_567), "1,234,567");
+        assert_eq!(units.format(123), "123");
+        let fitted = units.fit([10, 1_234_567].into_iter());
+        assert_eq!(fitted.column(10), "       10");
+        assert_eq!(Units::new().fit([1 << 40].into_iter()).width, 6);
+    }
+
+    #[test]
+    fn block_sizes() {
+        let (block_size, separators) = parse_block_size("M").unwrap();
+        assert_eq!(block_size.size, 1 << 20);
+        assert_eq!(block_size.suffix, "M");
+        assert!(!separators);
+        let (block_size, _) = parse_block_size("1K").unwrap();
+        assert_eq!((block_size.size, block_size.suffix.as_str()), (1024, ""));
+        let (block_size, _) = parse_block_size("kB").unwrap();
+        assert_eq!((block_size.size, block_size.suffix.as_str()), (1000, "kB"));
+        let (block_size, _) = parse_block_size("2MiB").unwrap();
+        assert_eq!(block_size.size, 2 << 20);
+        let (block_size, separators) = parse_block_size("'1").unwrap();
+        assert_eq!(block_size.size, 1);
+        assert!(separators);
+        assert!(parse_block_size("0").is_err());
+        assert!(parse_block_size("X").is_err());
+        assert!(parse_block_size("1Kb").is_err());
+        assert!(parse_block_size("20E").is_err());
+
+        let mut units = Units::new();
+        units.block_size = parse_block_size("M").ok().map(|(block_size, _)| block_size);
+        assert_eq!(units.format(1), "1M");
+        assert_eq!(units.format((1 << 20) + 1), "2M");
+        assert_eq!(units.format(0), "0M");
+    }
+}
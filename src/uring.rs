@@ -0,0 +1,154 @@
+use crate::walk::{DirEntry, Meta, STATX_MASK};
+use io_uring::{opcode, types, IoUring, Probe};
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Statx requests submitted to the ring at once
+pub const RING_ENTRIES: usize = 256;
+
+/// Set when io_uring or its statx opcode is missing, or when turned off
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RING: RefCell<Option<IoUring>> = const { RefCell::new(None) };
+}
+
+/// set_uring
+///
+/// Choose between batched statx through io_uring and one statx per entry.
+/// Enabling has no effect when the kernel lacks support.
+// only called through the library, by the benchmark
+#[allow(dead_code)]
+pub fn set_uring(enabled: bool) {
+    DISABLED.store(!enabled, Ordering::Relaxed);
+}
+
+/// stat_batch
+///
+/// Submit a statx for each entry relative to the directory descriptor and
+/// wait for all of them.  Symlinks are not followed.  Returns None when
+/// io_uring is unavailable so the caller falls back to statx per entry.  The
+/// ring is created once per thread and not retried after a failure.
+pub fn stat_batch(dirfd: libc::c_int, entries: &[DirEntry]) -> Option<Vec<io::Result<Meta>>> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    RING.with(|ring| {
+        let mut ring = ring.borrow_mut();
+        if ring.is_none() {
+            *ring = setup();
+        }
+        match ring.as_mut() {
+            Some(ring) => submit(ring, dirfd, entries),
+            None => {
+                DISABLED.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    })
+}
+
+/// setup
+///
+/// Create a ring and probe it for IORING_OP_STATX, added in Linux 5.6.
+fn setup() -> Option<IoUring> {
+    let ring = IoUring::new(RING_ENTRIES as u32).ok()?;
+    let mut probe = Probe::new();
+    ring.submitter().register_probe(&mut probe).ok()?;
+    probe.is_supported(opcode::Statx::CODE).then_some(ring)
+}
+
+fn submit(
+    ring: &mut IoUring,
+    dirfd: libc::c_int,
+    entries: &[DirEntry],
+) -> Option<Vec<io::Result<Meta>>> {
+    let mut buffers: Vec<libc::statx> = entries.iter().map(|_| unsafe { mem::zeroed() }).collect();
+    let mut results: Vec<Option<io::Result<Meta>>> = entries.iter().map(|_| None).collect();
+
+    for start in (0..entries.len()).step_by(RING_ENTRIES) {
+        let end = entries.len().min(start + RING_ENTRIES);
+        {
+            let mut queue = ring.submission();
+            for index in start..end {
+                let statx = opcode::Statx::new(
+                    types::Fd(dirfd),
+                    entries[index].name.as_ptr(),
+                    &mut buffers[index] as *mut libc::statx as *mut types::statx,
+                )
+                .flags(libc::AT_SYMLINK_NOFOLLOW | libc::AT_STATX_SYNC_AS_STAT)
+                .mask(STATX_MASK)
+                .build()
+                .user_data(index as u64);
+                // the queue holds RING_ENTRIES and is drained below
+                if unsafe { queue.push(&statx) }.is_err() {
+                    return abandon(buffers);
+                }
+            }
+        }
+
+        let mut pending = end - start;
+        while pending > 0 {
+            match ring.submit_and_wait(pending) {
+                Ok(_) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return abandon(buffers),
+            }
+            for completion in ring.completion() {
+                let index = completion.user_data() as usize;
+                results[index] = Some(match completion.result() {
+                    result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+                    _ => Ok(Meta::from_statx(&buffers[index])),
+                });
+                pending -= 1;
+            }
+        }
+    }
+
+    results.into_iter().collect()
+}
+
+/// abandon
+///
+/// Stop using the ring after an unexpected error.  The kernel may still write
+/// to requests in flight, so their buffers are leaked rather than freed.
+fn abandon(buffers: Vec<libc::statx>) -> Option<Vec<io::Result<Meta>>> {
+    mem::forget(buffers);
+    DISABLED.store(true, Ordering::Relaxed);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk::{Dir, Kind};
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn batch_matches_stat_at() {
+        let tmp_dir = TempDir::new("dsuring").unwrap();
+        for i in 0..300 {
+            fs::write(tmp_dir.path().join(format!("file{}", i)), vec![0; i]).unwrap();
+        }
+        fs::create_dir(tmp_dir.path().join("dir")).unwrap();
+
+        let mut dir = Dir::open(tmp_dir.path()).unwrap();
+        let entries: Vec<DirEntry> = dir.by_ref().map(|entry| entry.unwrap()).collect();
+        let batch = match stat_batch(dir.fd(), &entries) {
+            Some(batch) => batch,
+            // io_uring not permitted here
+            None => return,
+        };
+        assert_eq!(batch.len(), 301);
+        for (entry, meta) in entries.iter().zip(batch) {
+            let meta = meta.unwrap();
+            assert_eq!(meta, dir.stat_at(entry).unwrap());
+            if entry.name() == "dir" {
+                assert_eq!(meta.kind, Kind::Directory);
+            }
+        }
+    }
+}
@@ -0,0 +1,634 @@
+use std::ffi::OsStr;
+#[cfg(target_os = "windows")]
+use std::ffi::OsString;
+#[cfg(not(target_os = "windows"))]
+use std::ffi::{CStr, CString};
+#[cfg(target_os = "windows")]
+use std::fs;
+use std::fs::File;
+use std::io;
+#[cfg(not(target_os = "windows"))]
+use std::mem;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::io::FromRawFd;
+use std::path::Path;
+#[cfg(target_os = "windows")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(target_os = "windows")]
+use std::time::UNIX_EPOCH;
+
+/// Entries read from a directory before their metadata is requested.  Only
+/// the io_uring backend gains from more than one.
+#[cfg(all(feature = "uring", target_os = "linux"))]
+pub const BATCH: usize = crate::uring::RING_ENTRIES;
+#[cfg(not(all(feature = "uring", target_os = "linux")))]
+pub const BATCH: usize = 1;
+
+/// Set once statx is found missing so later calls go straight to fstatat
+#[cfg(target_os = "linux")]
+static STATX_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Fields requested from statx.  The device is always returned.
+#[cfg(target_os = "linux")]
+pub(crate) const STATX_MASK: libc::c_uint = libc::STATX_TYPE
+    | libc::STATX_NLINK
+    | libc::STATX_UID
+    | libc::STATX_INO
+    | libc::STATX_SIZE
+    | libc::STATX_BLOCKS
+    | libc::STATX_MTIME
+    | libc::STATX_BTIME
+    | libc::STATX_MNT_ID;
+
+/// Kind
+///
+/// File type of an entry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+/// Meta
+///
+/// The fields of a single statx or lstat needed for the calculations.  Size is
+/// the apparent size in bytes, blocks the disk usage in 512 byte blocks.
+/// Uid is the owner, 0 where unknown.  Times are seconds since the epoch.
+/// Birth time and the mount id are only known from statx and only on
+/// filesystems recording them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Meta {
+    pub kind: Kind,
+    pub dev: u64,
+    pub ino: u64,
+    pub nlink: u64,
+    pub uid: u32,
+    pub size: u64,
+    pub blocks: u64,
+    pub mtime: i64,
+    pub btime: Option<i64>,
+    pub mnt_id: Option<u64>,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Meta {
+    // field widths differ between architectures
+    #[allow(clippy::unnecessary_cast)]
+    fn from_stat(stat: &libc::stat) -> Meta {
+        Meta {
+            kind: kind_of(stat.st_mode),
+            dev: stat.st_dev as u64,
+            ino: stat.st_ino as u64,
+            nlink: stat.st_nlink as u64,
+            uid: stat.st_uid as u32,
+            size: stat.st_size as u64,
+            blocks: stat.st_blocks as u64,
+            mtime: stat.st_mtime as i64,
+            btime: None,
+            mnt_id: None,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub(crate) fn from_statx(stx: &libc::statx) -> Meta {
+        let known = |field| stx.stx_mask & field != 0;
+        Meta {
+            kind: kind_of(libc::mode_t::from(stx.stx_mode)),
+            dev: libc::makedev(stx.stx_dev_major, stx.stx_dev_minor),
+            ino: stx.stx_ino,
+            nlink: u64::from(stx.stx_nlink),
+            uid: stx.stx_uid,
+            size: stx.stx_size,
+            blocks: stx.stx_blocks,
+            mtime: stx.stx_mtime.tv_sec,
+            btime: known(libc::STATX_BTIME).then_some(stx.stx_btime.tv_sec),
+            mnt_id: known(libc::STATX_MNT_ID).then_some(stx.stx_mnt_id),
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn kind_of(mode: libc::mode_t) -> Kind {
+    match mode & libc::S_IFMT {
+        libc::S_IFREG => Kind::File,
+        libc::S_IFDIR => Kind::Directory,
+        libc::S_IFLNK => Kind::Symlink,
+        _ => Kind::Other,
+    }
+}
+
+/// statx
+///
+/// Metadata relative to a directory descriptor from statx.  Returns None when
+/// the kernel lacks statx, after which callers fall back to the stat family.
+#[cfg(target_os = "linux")]
+fn statx(fd: libc::c_int, name: &CStr, flags: libc::c_int) -> Option<io::Result<Meta>> {
+    if STATX_UNSUPPORTED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let mut stx: libc::statx = unsafe { mem::zeroed() };
+    let result = unsafe {
+        libc::statx(
+            fd,
+            name.as_ptr(),
+            flags | libc::AT_STATX_SYNC_AS_STAT,
+            STATX_MASK,
+            &mut stx,
+        )
+    };
+    if result == 0 {
+        return Some(Ok(Meta::from_statx(&stx)));
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        // seccomp filters of older container runtimes answer EPERM
+        Some(libc::ENOSYS) | Some(libc::EPERM) => {
+            STATX_UNSUPPORTED.store(true, Ordering::Relaxed);
+            None
+        }
+        _ => Some(Err(err)),
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Meta {
+    fn from_metadata(metadata: &fs::Metadata) -> Meta {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            Kind::Symlink
+        } else if file_type.is_dir() {
+            Kind::Directory
+        } else if file_type.is_file() {
+            Kind::File
+        } else {
+            Kind::Other
+        };
+        Meta {
+            kind,
+            dev: 0,
+            ino: 0,
+            nlink: 1,
+            uid: 0,
+            size: metadata.len(),
+            blocks: metadata.len().div_ceil(512),
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_secs() as i64),
+            btime: metadata
+                .created()
+                .ok()
+                .and_then(|created| created.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs() as i64),
+            mnt_id: None,
+        }
+    }
+}
+
+/// DirEntry
+///
+/// A name read from a directory, its inode number and its type if the
+/// filesystem reports one
+pub struct DirEntry {
+    #[cfg(not(target_os = "windows"))]
+    pub(crate) name: CString,
+    #[cfg(target_os = "windows")]
+    name: OsString,
+    pub ino: u64,
+    pub kind: Option<Kind>,
+}
+
+impl DirEntry {
+    /// new
+    ///
+    /// An entry produced by a backend other than a directory stream
+    #[cfg(not(target_os = "windows"))]
+    pub fn new(name: &OsStr, ino: u64, kind: Option<Kind>) -> io::Result<DirEntry> {
+        Ok(DirEntry {
+            name: CString::new(name.as_bytes())?,
+            ino,
+            kind,
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn new(name: &OsStr, ino: u64, kind: Option<Kind>) -> io::Result<DirEntry> {
+        Ok(DirEntry {
+            name: name.to_os_string(),
+            ino,
+            kind,
+        })
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    pub fn name(&self) -> &OsStr {
+        OsStr::from_bytes(self.name.to_bytes())
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+}
+
+/// stat
+///
+/// Metadata of an anchor.  Symlinks are followed since anchors are given on
+/// the command line.
+#[cfg(not(target_os = "windows"))]
+pub fn stat(path: &Path) -> io::Result<Meta> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    #[cfg(target_os = "linux")]
+    if let Some(result) = statx(libc::AT_FDCWD, &cpath, 0) {
+        return result;
+    }
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::stat(cpath.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Meta::from_stat(&stat))
+}
+
+#[cfg(target_os = "windows")]
+pub fn stat(path: &Path) -> io::Result<Meta> {
+    Ok(Meta::from_metadata(&fs::metadata(path)?))
+}
+
+/// lstat
+///
+/// Metadata of a path listed with --files-from.  Symlinks are not followed,
+/// as in a walk.
+#[cfg(not(target_os = "windows"))]
+pub fn lstat(path: &Path) -> io::Result<Meta> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    #[cfg(target_os = "linux")]
+    if let Some(result) = statx(libc::AT_FDCWD, &cpath, libc::AT_SYMLINK_NOFOLLOW) {
+        return result;
+    }
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::lstat(cpath.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Meta::from_stat(&stat))
+}
+
+#[cfg(target_os = "windows")]
+pub fn lstat(path: &Path) -> io::Result<Meta> {
+    Ok(Meta::from_metadata(&fs::symlink_metadata(path)?))
+}
+
+/// Dir
+///
+/// An open directory stream.  Each entry costs one readdir record and at most
+/// one statx, or fstatat without statx, relative to the open directory.
+/// Directories are opened relative to their parent, so paths longer than
+/// PATH_MAX are walked.  Windows uses std::fs behind the same interface.
+#[cfg(not(target_os = "windows"))]
+pub struct Dir {
+    stream: *mut libc::DIR,
+}
+
+#[cfg(target_os = "windows")]
+pub struct Dir {
+    path: PathBuf,
+    contents: fs::ReadDir,
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Dir {
+    /// open
+    ///
+    /// Open an anchor directory by path
+    pub fn open(path: &Path) -> io::Result<Dir> {
+        let cpath = CString::new(path.as_os_str().as_bytes())?;
+        let fd = unsafe {
+            libc::open(
+                cpath.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        Dir::from_fd(fd)
+    }
+
+    /// open_at
+    ///
+    /// Open a child directory relative to this one without following symlinks
+    pub fn open_at(&self, entry: &DirEntry) -> io::Result<Dir> {
+        let fd = unsafe {
+            libc::openat(
+                self.fd(),
+                entry.name.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        };
+        Dir::from_fd(fd)
+    }
+
+    /// open_file_at
+    ///
+    /// Open a file of this directory for reading without following symlinks
+    pub fn open_file_at(&self, entry: &DirEntry) -> io::Result<File> {
+        let fd = unsafe {
+            libc::openat(
+                self.fd(),
+                entry.name.as_ptr(),
+                libc::O_RDONLY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn from_fd(fd: libc::c_int) -> io::Result<Dir> {
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = unsafe { libc::fdopendir(fd) };
+        if stream.is_null() {
+            let err = io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+        Ok(Dir { stream })
+    }
+
+    pub(crate) fn fd(&self) -> libc::c_int {
+        unsafe { libc::dirfd(self.stream) }
+    }
+
+    /// stat
+    ///
+    /// Metadata of the directory itself
+    pub fn stat(&self) -> io::Result<Meta> {
+        #[cfg(target_os = "linux")]
+        if let Some(result) = statx(self.fd(), c"", libc::AT_EMPTY_PATH) {
+            return result;
+        }
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(self.fd(), &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Meta::from_stat(&stat))
+    }
+
+    /// stat_at
+    ///
+    /// Metadata of an entry of this directory.  Symlinks are not followed.
+    pub fn stat_at(&self, entry: &DirEntry) -> io::Result<Meta> {
+        #[cfg(target_os = "linux")]
+        if let Some(result) = statx(self.fd(), &entry.name, libc::AT_SYMLINK_NOFOLLOW) {
+            return result;
+        }
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        let result = unsafe {
+            libc::fstatat(
+                self.fd(),
+                entry.name.as_ptr(),
+                &mut stat,
+                libc::AT_SYMLINK_NOFOLLOW,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Meta::from_stat(&stat))
+    }
+}
+
+impl Dir {
+    /// stat_batch
+    ///
+    /// Metadata of several entries of this directory.  With the uring feature
+    /// the requests are submitted to the kernel at once, otherwise or when
+    /// io_uring is unavailable each entry costs one stat_at.
+    pub fn stat_batch(&self, entries: &[DirEntry]) -> Vec<io::Result<Meta>> {
+        #[cfg(all(feature = "uring", target_os = "linux"))]
+        if entries.len() > 1 {
+            if let Some(metas) = crate::uring::stat_batch(self.fd(), entries) {
+                return metas;
+            }
+        }
+        entries.iter().map(|entry| self.stat_at(entry)).collect()
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Iterator for Dir {
+    type Item = io::Result<DirEntry>;
+
+    /// Skips . and ..  The end of the stream and an error are told apart by
+    /// errno.
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        loop {
+            unsafe { *libc::__errno_location() = 0 };
+            let dirent = unsafe { libc::readdir(self.stream) };
+            if dirent.is_null() {
+                let err = io::Error::last_os_error();
+                return match err.raw_os_error() {
+                    Some(0) | None => None,
+                    Some(_) => Some(Err(err)),
+                };
+            }
+
+            let (name, d_ino, d_type) = unsafe {
+                (
+                    CStr::from_ptr((*dirent).d_name.as_ptr()),
+                    (*dirent).d_ino,
+                    (*dirent).d_type,
+                )
+            };
+            let bytes = name.to_bytes();
+            if bytes == b"." || bytes == b".." {
+                continue;
+            }
+
+            let kind = match d_type {
+                libc::DT_REG => Some(Kind::File),
+                libc::DT_DIR => Some(Kind::Directory),
+                libc::DT_LNK => Some(Kind::Symlink),
+                libc::DT_UNKNOWN => None,
+                _ => Some(Kind::Other),
+            };
+            return Some(Ok(DirEntry {
+                name: name.to_owned(),
+                // field widths differ between platforms
+                #[allow(clippy::unnecessary_cast)]
+                ino: d_ino as u64,
+                kind,
+            }));
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+impl Drop for Dir {
+    fn drop(&mut self) {
+        unsafe { libc::closedir(self.stream) };
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Dir {
+    pub fn open(path: &Path) -> io::Result<Dir> {
+        Ok(Dir {
+            path: path.to_path_buf(),
+            contents: fs::read_dir(path)?,
+        })
+    }
+
+    pub fn open_at(&self, entry: &DirEntry) -> io::Result<Dir> {
+        Dir::open(&self.path.join(&entry.name))
+    }
+
+    pub fn open_file_at(&self, entry: &DirEntry) -> io::Result<File> {
+        File::open(self.path.join(&entry.name))
+    }
+
+    pub fn stat(&self) -> io::Result<Meta> {
+        Ok(Meta::from_metadata(&fs::metadata(&self.path)?))
+    }
+
+    pub fn stat_at(&self, entry: &DirEntry) -> io::Result<Meta> {
+        Ok(Meta::from_metadata(&fs::symlink_metadata(
+            self.path.join(&entry.name),
+        )?))
+    }
+}
+
+#[cfg(target_os = "windows")]
+impl Iterator for Dir {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        let entry = match self.contents.next()? {
+            Ok(entry) => entry,
+            Err(err) => return Some(Err(err)),
+        };
+        let kind = entry.file_type().ok().map(|file_type| {
+            if file_type.is_symlink() {
+                Kind::Symlink
+            } else if file_type.is_dir() {
+                Kind::Directory
+            } else if file_type.is_file() {
+                Kind::File
+            } else {
+                Kind::Other
+            }
+        });
+        Some(Ok(DirEntry {
+            name: entry.file_name(),
+            ino: 0,
+            kind,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn entries_and_stat() {
+        let tmp_dir = TempDir::new("dswalk").unwrap();
+        fs::create_dir(tmp_dir.path().join("dir")).unwrap();
+        fs::write(tmp_dir.path().join("file"), b"12345").unwrap();
+
+        let dir = Dir::open(tmp_dir.path()).unwrap();
+        assert_eq!(dir.stat().unwrap().kind, Kind::Directory);
+
+        let mut dir = Dir::open(tmp_dir.path()).unwrap();
+        let mut entries: Vec<DirEntry> = dir.by_ref().map(|entry| entry.unwrap()).collect();
+        entries.sort_by(|a, b| a.name().cmp(b.name()));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name(), "dir");
+        assert_eq!(entries[1].name(), "file");
+
+        let meta = dir.stat_at(&entries[1]).unwrap();
+        assert_eq!(meta.kind, Kind::File);
+        assert_eq!(meta.size, 5);
+
+        let mut child = dir.open_at(&entries[0]).unwrap();
+        assert!(child.next().is_none());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn symlink_not_followed() {
+        let tmp_dir = TempDir::new("dswalk").unwrap();
+        fs::create_dir(tmp_dir.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink(tmp_dir.path().join("dir"), tmp_dir.path().join("link"))
+            .unwrap();
+
+        let mut dir = Dir::open(tmp_dir.path()).unwrap();
+        let entries: Vec<DirEntry> = dir.by_ref().map(|entry| entry.unwrap()).collect();
+        let link = entries.iter().find(|entry| entry.name() == "link").unwrap();
+        assert_eq!(dir.stat_at(link).unwrap().kind, Kind::Symlink);
+        assert!(dir.open_at(link).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn longer_than_path_max() {
+        let tmp_dir = TempDir::new("dswalk").unwrap();
+        let name = "d".repeat(200);
+        let mut dir = Dir::open(tmp_dir.path()).unwrap();
+        let mut depth = 0;
+        // 30 levels of 200 bytes exceeds PATH_MAX of 4096
+        while depth < 30 {
+            let cname = CString::new(name.clone()).unwrap();
+            unsafe { libc::mkdirat(dir.fd(), cname.as_ptr(), 0o755) };
+            let entry = dir.find(|entry| entry.as_ref().unwrap().name() == name.as_str());
+            dir = dir.open_at(&entry.unwrap().unwrap()).unwrap();
+            depth += 1;
+        }
+        assert_eq!(dir.stat().unwrap().kind, Kind::Directory);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn statx_fallback_agrees() {
+        let tmp_dir = TempDir::new("dswalk").unwrap();
+        fs::write(tmp_dir.path().join("file"), b"12345").unwrap();
+        let cpath = CString::new(tmp_dir.path().join("file").as_os_str().as_bytes()).unwrap();
+
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        unsafe { libc::stat(cpath.as_ptr(), &mut stat) };
+        let fallback = Meta::from_stat(&stat);
+        let meta = match statx(libc::AT_FDCWD, &cpath, 0) {
+            Some(meta) => meta.unwrap(),
+            None => return,
+        };
+        assert_eq!(meta.kind, fallback.kind);
+        assert_eq!(meta.dev, fallback.dev);
+        assert_eq!(meta.ino, fallback.ino);
+        assert_eq!(meta.size, 5);
+        assert_eq!(fallback.btime, None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mount_id_differs_across_mounts() {
+        let root = stat(Path::new("/")).unwrap();
+        let proc = stat(Path::new("/proc")).unwrap();
+        if root.mnt_id.is_some() && proc.mnt_id.is_some() {
+            assert_ne!(root.mnt_id, proc.mnt_id);
+        }
+    }
+
+    #[test]
+    fn stat_error() {
+        assert!(stat(Path::new("/doesnotexist")).is_err());
+        assert!(Dir::open(Path::new("/doesnotexist")).is_err());
+    }
+}
@@ -1,10 +1,13 @@
 extern crate clap;
 extern crate ds;
+extern crate flate2;
+extern crate tar;
 extern crate tempdir;
+extern crate zip;
 #[cfg(target_os = "linux")]
 use clap::App;
 #[cfg(target_os = "linux")]
-use ds::traverse;
+use ds::{DSGroup, Partial};
 #[cfg(target_os = "linux")]
 use std::collections::BTreeMap;
 #[cfg(target_os = "linux")]
@@ -15,6 +18,12 @@ use std::io::Write;
 use std::os::unix;
 use std::process::Command;
 #[cfg(target_os = "linux")]
+use std::process::Stdio;
+#[cfg(target_os = "linux")]
+use std::thread;
+#[cfg(target_os = "linux")]
+use std::time::Duration;
+#[cfg(target_os = "linux")]
 use tempdir::TempDir;
 
 #[cfg(test)]
@@ -40,6 +49,59 @@ fn simple_verbose() {
     }
 }
 
+/// Whether the process has its own SIGINT handler, from the caught signal
+/// mask in /proc where signal 2 is the second bit
+#[cfg(target_os = "linux")]
+fn catches_sigint(pid: u32) -> bool {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("SigCgt:"))
+        .and_then(|mask| u64::from_str_radix(mask.trim(), 16).ok())
+        .is_some_and(|mask| mask & 0x2 != 0)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn simple_interrupted() {
+    setup();
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let file = tmp_dir.path().join("file");
+    fs::write(&file, vec![0u8; 100]).unwrap();
+
+    // the scan waits on the list in the pipe until the signal has arrived
+    let mut child = Command::new("target/debug/ds")
+        .arg("--files-from")
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    for _ in 0..1000 {
+        if catches_sigint(child.id()) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(catches_sigint(child.id()));
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(child.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "{}", file.display()).unwrap();
+    drop(stdin);
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(130));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Results are partial, interrupted"));
+    assert!(stdout.contains(&format!(" {}", file.display())));
+}
+
 #[test]
 fn simple_help() {
     setup();
@@ -95,7 +157,7 @@ fn sample_directories() {
             let mut tmpfile = fs::File::create(filename).unwrap();
             let mut contents = "Random strings".to_string();
             for _ in 1..i {
-                contents = format!("{}{}", contents, "and more ".to_string());
+                contents = format!("{}{}", contents, "and more ");
             }
             writeln!(tmpfile, "{}", contents).unwrap();
         }
@@ -107,7 +169,7 @@ fn sample_directories() {
     );
 
     let matches = App::new("DSintegration").get_matches();
-    let disk_space = traverse(
+    let disk_space = DSGroup::new().calculate(
         &vec![tmp_dir.path().to_string_lossy().to_string()],
         &matches,
     );
@@ -144,7 +206,7 @@ fn sample_permission_denied() {
             let mut tmpfile = fs::File::create(filename).unwrap();
             let mut contents = "Random strings".to_string();
             for _ in 1..i {
-                contents = format!("{}{}", contents, "and more ".to_string());
+                contents = format!("{}{}", contents, "and more ");
             }
             writeln!(tmpfile, "{}", contents).unwrap();
         }
@@ -152,10 +214,14 @@ fn sample_permission_denied() {
 
     let path = format!("{}/a/b", tmp_dir.path().display());
     dbg!(&path);
-    let _ = Command::new("chmod").arg("0").arg(path).output();
+    let _ = Command::new("chmod").arg("0").arg(&path).output();
+    if fs::read_dir(&path).is_ok() {
+        eprintln!("skipped sample_permission_denied: permissions are not enforced, e.g. as root");
+        return;
+    }
 
     let matches = App::new("DSintegration").get_matches();
-    let disk_space = traverse(
+    let disk_space = DSGroup::new().calculate(
         &vec![tmp_dir.path().to_string_lossy().to_string()],
         &matches,
     );
@@ -163,3 +229,654 @@ fn sample_permission_denied() {
     // tmp_dir.close().unwrap();
     assert_eq!(disk_space, expected);
 }
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_max_entries() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let tmppath = tmp_dir.path().to_owned();
+
+    fs::create_dir_all(tmp_dir.path().join("a")).unwrap();
+    fs::create_dir_all(tmp_dir.path().join("b")).unwrap();
+    for subdir in &["a", "b"] {
+        for i in 1..6 {
+            let filename = tmp_dir.path().join(subdir).join(format!("sample{}", i));
+            let mut tmpfile = fs::File::create(filename).unwrap();
+            writeln!(tmpfile, "Random strings").unwrap();
+        }
+    }
+
+    let args = vec!["ds", "--max-entries", "4"];
+    let matches = App::new("DSintegration")
+        .arg(
+            clap::Arg::with_name("max-entries")
+                .long("max-entries")
+                .takes_value(true),
+        )
+        .get_matches_from(args);
+    let mut group = DSGroup::new();
+    let disk_space = group.calculate(&vec![tmppath.to_string_lossy().to_string()], &matches);
+
+    assert_eq!(group.budget.exhausted, Some(Partial::MaxEntries));
+    assert_eq!(group.budget.entries, 4);
+    // the anchor and its first directory stop midway through their batches
+    assert!(group.incomplete.contains(&tmppath));
+    assert_eq!(group.incomplete.len(), 2);
+    // one directory and three of its files are counted, the rest unseen
+    let files: Vec<u64> = disk_space
+        .iter()
+        .filter(|(path, _)| path.contains("sample"))
+        .map(|(_, size)| *size)
+        .collect();
+    assert_eq!(files.len(), 3);
+    // directory sizes are still rolled up from the entries seen
+    assert_eq!(
+        disk_space[&tmppath.to_string_lossy().to_string()],
+        files.iter().sum::<u64>()
+    );
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_multiple_anchors() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+
+    for subdir in &["a", "b"] {
+        fs::create_dir_all(tmp_dir.path().join(subdir)).unwrap();
+        let filename = tmp_dir.path().join(subdir).join("sample1");
+        let mut tmpfile = fs::File::create(filename).unwrap();
+        writeln!(tmpfile, "Random strings").unwrap();
+    }
+
+    let anchors = vec![
+        tmp_dir.path().join("a").to_string_lossy().to_string(),
+        tmp_dir.path().join("b").to_string_lossy().to_string(),
+    ];
+    let matches = App::new("DSintegration").get_matches();
+    let disk_space = DSGroup::new().calculate(&anchors, &matches);
+
+    assert_eq!(disk_space[&anchors[0]], 15);
+    assert_eq!(disk_space[&anchors[1]], 15);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_anchor_fstype() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    fs::write(tmp_dir.path().join("file"), vec![0u8; 10]).unwrap();
+    let run = |args: &[&str]| {
+        Command::new("target/debug/ds")
+            .args(args)
+            .arg("/proc/self")
+            .arg(tmp_dir.path())
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["-a"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Skipping /proc/self: proc filesystem")
+    );
+    assert!(!stdout.contains("/proc/self"));
+    assert!(stdout.contains(&format!(" {}/file", tmp_dir.path().display())));
+
+    // the include list admits proc and only limits the mounts crossed into
+    let output = run(&["-a", "--include-fstype", "proc"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Skipping"));
+    assert!(stdout.contains(" /proc/self"));
+    assert!(stdout.contains(&format!(" {}/file", tmp_dir.path().display())));
+
+    let output = run(&["-a", "--include-fstype", "nfs"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("/proc/self"));
+    assert!(stdout.contains(&format!(" {}/file", tmp_dir.path().display())));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_export_anchors() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    fs::create_dir_all(tmp_dir.path().join("a")).unwrap();
+    fs::create_dir_all(tmp_dir.path().join("b")).unwrap();
+    let export = tmp_dir.path().join("export.json");
+
+    let output = Command::new("target/debug/ds")
+        .arg("--export-ncdu")
+        .arg(&export)
+        .arg(tmp_dir.path().join("a"))
+        .arg(tmp_dir.path().join("b"))
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("ncdu exports hold one directory"));
+    assert!(!export.exists());
+
+    let output = Command::new("target/debug/ds")
+        .arg("--export-ncdu")
+        .arg(&export)
+        .arg(tmp_dir.path().join("a"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(export.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_hard_links() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let tmppath = tmp_dir.path().to_owned();
+
+    let mut tmpfile = fs::File::create(tmp_dir.path().join("sample1")).unwrap();
+    writeln!(tmpfile, "Random strings").unwrap();
+    fs::hard_link(
+        tmp_dir.path().join("sample1"),
+        tmp_dir.path().join("sample2"),
+    )
+    .unwrap();
+
+    let matches = App::new("DSintegration").get_matches();
+    let disk_space =
+        DSGroup::new().calculate(&vec![tmppath.to_string_lossy().to_string()], &matches);
+
+    assert_eq!(disk_space[&tmppath.to_string_lossy().to_string()], 15);
+    assert_eq!(disk_space.len(), 2);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_born_filters() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let tmppath = tmp_dir.path().to_owned();
+
+    let mut tmpfile = fs::File::create(tmp_dir.path().join("sample1")).unwrap();
+    writeln!(tmpfile, "Random strings").unwrap();
+    let btime = match ds::stat(&tmp_dir.path().join("sample1")).unwrap().btime {
+        Some(btime) => btime,
+        // filesystem does not record birth time
+        None => return,
+    };
+
+    let calculate = |option: &str, time: i64| {
+        let args = vec!["ds".to_string(), option.to_string(), format!("@{}", time)];
+        let matches = App::new("DSintegration")
+            .arg(
+                clap::Arg::with_name("born-before")
+                    .long("born-before")
+                    .takes_value(true),
+            )
+            .arg(
+                clap::Arg::with_name("born-after")
+                    .long("born-after")
+                    .takes_value(true),
+            )
+            .get_matches_from(args);
+        DSGroup::new().calculate(&vec![tmppath.to_string_lossy().to_string()], &matches)
+    };
+
+    let anchor = tmppath.to_string_lossy().to_string();
+    assert_eq!(calculate("--born-before", btime + 60)[&anchor], 15);
+    assert_eq!(calculate("--born-after", btime - 60)[&anchor], 15);
+    assert_eq!(calculate("--born-before", btime - 60)[&anchor], 0);
+    assert_eq!(calculate("--born-after", btime + 60)[&anchor], 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_into_archives() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let tmppath = tmp_dir.path().to_owned();
+
+    let gz = fs::File::create(tmp_dir.path().join("backup.tar.gz")).unwrap();
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        gz,
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(1000);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "./var/log/big.log", &[0u8; 1000][..])
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let zip = fs::File::create(tmp_dir.path().join("docs.zip")).unwrap();
+    let mut writer = zip::ZipWriter::new(zip);
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    writer.start_file("readme.txt", options).unwrap();
+    writer.write_all(&[b'x'; 300]).unwrap();
+    writer.finish().unwrap();
+
+    fs::write(tmp_dir.path().join("broken.zip"), b"not a zip").unwrap();
+
+    let matches = App::new("DSintegration")
+        .arg(clap::Arg::with_name("into-archives").long("into-archives"))
+        .get_matches_from(vec!["ds", "--into-archives"]);
+    let disk_space =
+        DSGroup::new().calculate(&vec![tmppath.to_string_lossy().to_string()], &matches);
+
+    let path = |name: &str| format!("{}/{}", tmppath.display(), name);
+    assert_eq!(disk_space[&path("backup.tar.gz!/var/log/big.log")], 1000);
+    assert_eq!(disk_space[&path("backup.tar.gz!/var")], 1000);
+    assert_eq!(disk_space[&path("backup.tar.gz!")], 1000);
+    assert_eq!(disk_space[&path("docs.zip!/readme.txt")], 300);
+    assert_eq!(disk_space[&path("broken.zip")], 9);
+    assert!(!disk_space.contains_key(&path("broken.zip!")));
+    // the anchor holds the archives as stored, not their contents
+    let stored: u64 = ["backup.tar.gz", "docs.zip", "broken.zip"]
+        .iter()
+        .map(|name| disk_space[&path(name)])
+        .sum();
+    assert_eq!(disk_space[&tmppath.to_string_lossy().to_string()], stored);
+
+    // an archive given as an anchor is listed the same way
+    let disk_space = DSGroup::new().calculate(&vec![path("backup.tar.gz")], &matches);
+    assert_eq!(disk_space[&path("backup.tar.gz!/var/log/big.log")], 1000);
+    assert_eq!(disk_space[&path("backup.tar.gz!")], 1000);
+    let archive = fs::metadata(path("backup.tar.gz")).unwrap().len();
+    assert_eq!(disk_space[&path("backup.tar.gz")], archive);
+    let output = Command::new("target/debug/ds")
+        .args(["--into-archives", "-a"])
+        .arg(path("backup.tar.gz"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!(" {}", path("backup.tar.gz!/var/log/big.log"))));
+}
+
+/// Build an image of the contents of source with mke2fs.  Returns false when
+/// mke2fs is missing or too old for -d.
+#[cfg(target_os = "linux")]
+fn make_image(source: &std::path::Path, image: &std::path::Path, fstype: &str) -> bool {
+    Command::new("mke2fs")
+        .args(["-q", "-F", "-b", "1024", "-t", fstype, "-d"])
+        .arg(source)
+        .arg(image)
+        .arg("16M")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_image() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let source = tmp_dir.path().join("source");
+
+    // enough entries to spread a directory over indirect blocks of ext2
+    fs::create_dir_all(source.join("spool")).unwrap();
+    for i in 0..400 {
+        let name = format!("message-with-a-long-name-{:04}", i);
+        fs::write(source.join("spool").join(name), vec![b'x'; i]).unwrap();
+    }
+    fs::create_dir_all(source.join("a/b/c")).unwrap();
+    fs::write(source.join("a/b/c/big"), vec![0u8; 300_000]).unwrap();
+    fs::write(source.join("a/small"), b"Random strings\n").unwrap();
+    unix::fs::symlink("a/small", source.join("link")).unwrap();
+
+    let matches = App::new("DSintegration").get_matches_from(vec!["ds"]);
+    let live = DSGroup::new().calculate(&vec![source.to_string_lossy().to_string()], &matches);
+
+    for fstype in &["ext2", "ext3", "ext4"] {
+        let image = tmp_dir.path().join(format!("{}.img", fstype));
+        if !make_image(&source, &image, fstype) {
+            return;
+        }
+        let matches = App::new("DSintegration")
+            .arg(
+                clap::Arg::with_name("image")
+                    .long("image")
+                    .takes_value(true),
+            )
+            .get_matches_from(vec!["ds", "--image", &image.to_string_lossy()]);
+        let mut group = DSGroup::new();
+        let scanned = group.calculate(&vec![image.to_string_lossy().to_string()], &matches);
+
+        let scanned: BTreeMap<String, u64> = scanned
+            .into_iter()
+            .filter(|(path, _)| !path.contains("lost+found"))
+            .map(|(path, size)| {
+                let image = image.to_string_lossy().to_string();
+                (path.replacen(&image, &source.to_string_lossy(), 1), size)
+            })
+            .collect();
+        assert_eq!(scanned, live, "{}", fstype);
+        assert_eq!(
+            group.mounts.filesystem_type(&image),
+            Some(*fstype),
+            "{}",
+            fstype
+        );
+        assert!(group.filesystems[&image.to_string_lossy().to_string()].used > 0);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_image_not_ext() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let image = tmp_dir.path().join("disk.img");
+    fs::write(&image, vec![0u8; 8192]).unwrap();
+
+    let matches = App::new("DSintegration")
+        .arg(
+            clap::Arg::with_name("image")
+                .long("image")
+                .takes_value(true),
+        )
+        .get_matches_from(vec!["ds", "--image", &image.to_string_lossy()]);
+    let disk_space = DSGroup::new().calculate(&vec![image.to_string_lossy().to_string()], &matches);
+    assert!(disk_space.is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_image_loop() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let source = tmp_dir.path().join("source");
+    fs::create_dir_all(source.join("a")).unwrap();
+    fs::write(source.join("a/file"), vec![0u8; 5000]).unwrap();
+    let image = tmp_dir.path().join("loop.img");
+    if !make_image(&source, &image, "ext2") {
+        return;
+    }
+    // link a directory below itself, as a corrupt image may
+    let linked = Command::new("debugfs")
+        .args(["-w", "-R", "link /a /a/loop"])
+        .arg(&image)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if !linked {
+        return;
+    }
+
+    let output = Command::new("target/debug/ds")
+        .args(["-v", "-a", "--image"])
+        .arg(&image)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let file = image.join("a/file");
+    assert!(stdout.contains(&format!(" {}\n", file.to_string_lossy())));
+    assert!(stderr.contains("corrupt directory"));
+    assert!(!stdout.contains("loop/file"));
+}
+
+/// Serve ListObjectsV2 pages of a bucket, one request per connection.  The
+/// first page continues with a token naming the second.
+#[cfg(target_os = "linux")]
+fn mock_s3(pages: Vec<String>) -> (String, thread::JoinHandle<Vec<String>>) {
+    use std::io::{BufRead, BufReader};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = vec![];
+        for page in pages {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            while reader.read_line(&mut request).unwrap() > 2 {}
+            requests.push(request);
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/xml\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                page.len(),
+                page
+            )
+            .unwrap();
+        }
+        requests
+    });
+    (endpoint, handle)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_s3() {
+    let object = |key: &str, size: u64| {
+        format!(
+            "<Contents><Key>{}</Key><LastModified>2024-01-02T03:04:05.000Z</LastModified><Size>{}</Size></Contents>",
+            key, size
+        )
+    };
+    let pages = vec![
+        format!(
+            "<ListBucketResult><IsTruncated>true</IsTruncated>{}{}<NextContinuationToken>page2</NextContinuationToken></ListBucketResult>",
+            object("data/logs/a.log", 100),
+            object("data/logs/", 0)
+        ),
+        format!(
+            "<ListBucketResult><IsTruncated>false</IsTruncated>{}{}</ListBucketResult>",
+            object("data/logs/b.log", 50),
+            object("data/readme", 7)
+        ),
+    ];
+    let (endpoint, server) = mock_s3(pages);
+
+    let matches = App::new("DSintegration")
+        .arg(
+            clap::Arg::with_name("s3-endpoint")
+                .long("s3-endpoint")
+                .takes_value(true),
+        )
+        .get_matches_from(vec!["ds", "--s3-endpoint", &endpoint]);
+    let mut group = DSGroup::new();
+    let disk_space = group.calculate(&vec!["s3://bucket/data".to_string()], &matches);
+
+    assert_eq!(disk_space["s3://bucket/data"], 157);
+    assert_eq!(disk_space["s3://bucket/data/logs"], 150);
+    assert_eq!(disk_space["s3://bucket/data/logs/a.log"], 100);
+    assert_eq!(disk_space["s3://bucket/data/readme"], 7);
+    assert_eq!(group.filesystems["s3://bucket/data"].used, 157);
+    assert_eq!(
+        group
+            .mounts
+            .filesystem_type(std::path::Path::new("s3://bucket/data")),
+        Some("s3")
+    );
+
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("GET /bucket?list-type=2&prefix=data%2F HTTP/1.1"));
+    assert!(requests[1].contains("continuation-token=page2"));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_files_from() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let tmppath = tmp_dir.path().to_owned();
+    let path = |name: &str| format!("{}/{}", tmppath.display(), name);
+
+    fs::create_dir_all(path("a/b")).unwrap();
+    fs::write(path("a/b/one"), vec![0u8; 100]).unwrap();
+    fs::write(path("a/two"), vec![0u8; 20]).unwrap();
+    fs::write(path("a/unlisted"), vec![0u8; 5000]).unwrap();
+    fs::hard_link(path("a/two"), path("a/b/link")).unwrap();
+    unix::fs::symlink(path("a/two"), path("a/symlink")).unwrap();
+
+    let list = tmp_dir.path().join("list");
+    let names = [
+        "a/b/one",
+        "a/two",
+        "a/b/link",
+        "a/symlink",
+        "a/missing",
+        "a/b/",
+    ];
+    let listed: Vec<String> = names.iter().map(|name| path(name)).collect();
+    fs::write(&list, listed.join("\0")).unwrap();
+
+    let matches = App::new("DSintegration")
+        .arg(clap::Arg::with_name("null").long("null"))
+        .get_matches_from(vec!["ds", "--null"]);
+    let mut group = DSGroup::new();
+    let disk_space = group.calculate_files(&list.to_string_lossy(), &matches);
+
+    assert_eq!(disk_space[&path("a/b/one")], 100);
+    assert_eq!(disk_space[&path("a/two")], 20);
+    assert!(!disk_space.contains_key(&path("a/b/link")));
+    assert!(!disk_space.contains_key(&path("a/symlink")));
+    assert!(!disk_space.contains_key(&path("a/unlisted")));
+    assert_eq!(disk_space[&path("a/b")], 100);
+    assert_eq!(disk_space[&path("a")], 120);
+    assert_eq!(disk_space[&tmppath.to_string_lossy().to_string()], 120);
+    assert_eq!(disk_space["/"], 120);
+    assert_eq!(group.ve.count, 1);
+    assert!(group.filesystem(&path("a")).is_some());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sample_report_imports() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    let du = tmp_dir.path().join("du.txt");
+    fs::write(
+        &du,
+        "2048\t/srv/a\n1024\t/srv/a/b\n4096\t/srv/c\n6144\t/srv\n",
+    )
+    .unwrap();
+    let ncdu = tmp_dir.path().join("ncdu.json");
+    fs::write(
+        &ncdu,
+        r#"[1,0,{"progname":"ncdu"},[{"name":"/srv","asize":0},{"name":"big","asize":3072}]]"#,
+    )
+    .unwrap();
+
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["report", "-n", "2", "-e", "/srv/c", "--import-du"])
+        .arg(&du)
+        .output()
+    {
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "    6K /srv\n    2K /srv/a\n"
+        );
+    }
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["report", "--import-ncdu"])
+        .arg(&ncdu)
+        .output()
+    {
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "    3K /srv\n    3K /srv/big\n"
+        );
+    }
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["report", "--import-ncdu"])
+        .arg(&du)
+        .output()
+    {
+        assert_eq!(output.status.code(), Some(1));
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn sample_formats() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    fs::create_dir(tmp_dir.path().join("a")).unwrap();
+    fs::write(tmp_dir.path().join("a/b"), vec![0u8; 3000]).unwrap();
+    fs::write(tmp_dir.path().join("c,d"), vec![0u8; 10]).unwrap();
+
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["--format", "csv", "-a"])
+        .arg(tmp_dir.path())
+        .output()
+    {
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines[0], "size,type,count,mtime,btime,path");
+        assert!(lines[1].starts_with("3010,directory,4,"));
+        assert!(lines[2..4]
+            .iter()
+            .any(|line| line.starts_with("3000,directory,2,")));
+        assert!(lines[4].starts_with("10,file,1,"));
+        assert!(lines[4].ends_with("/c,d\""));
+    }
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["--format", "null", "-n", "1", "-r"])
+        .arg(tmp_dir.path())
+        .output()
+    {
+        assert!(output.status.success());
+        assert_eq!(
+            output.stdout,
+            format!("3010\t{}\0", tmp_dir.path().display()).into_bytes()
+        );
+    }
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args([
+            "--output-format",
+            "{size} {count} {depth} {type} {pct_parent}",
+            "-a",
+        ])
+        .arg(tmp_dir.path())
+        .output()
+    {
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().skip(3).collect();
+        assert_eq!(lines[0], "3010 4 0 directory -");
+        assert!(lines.contains(&"3000 1 2 file 100.0%"));
+        assert!(lines.contains(&"10 1 1 file 0.3%"));
+    }
+    if let Ok(output) = Command::new("target/debug/ds")
+        .args(["--tree", "--bytes", "--children", "1", "-c", "none"])
+        .arg(tmp_dir.path())
+        .output()
+    {
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().skip(3).collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("  3010 100.0% ██████████ /"));
+        assert_eq!(lines[1], "  3000  99.7% ██████████ ├── a");
+        assert_eq!(lines[2], "  3000 100.0% ██████████ │   └── b");
+        assert_eq!(lines[3], "    10   0.3%            └── (1 more)");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn color_choices() {
+    let tmp_dir = TempDir::new("/tmp/dstest").unwrap();
+    fs::write(tmp_dir.path().join("a"), vec![0u8; 10]).unwrap();
+    let run = |args: &[&str], env: &[(&str, &str)]| {
+        let output = Command::new("target/debug/ds")
+            .args(args)
+            .arg(tmp_dir.path())
+            .env_remove("NO_COLOR")
+            .env_remove("CLICOLOR_FORCE")
+            .envs(env.iter().copied())
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = run(&[], &[]);
+    assert!(!stdout.contains('\x1b'));
+    let stdout = run(&["--color=always"], &[("NO_COLOR", "1")]);
+    assert!(stdout.contains("\x1b[1;33m    10\x1b[0m"));
+    let stdout = run(&["--theme", "red"], &[("CLICOLOR_FORCE", "1")]);
+    assert!(stdout.contains("\x1b[1;31m    10\x1b[0m"));
+    let stdout = run(&["--color", "never"], &[("CLICOLOR_FORCE", "1")]);
+    assert!(!stdout.contains('\x1b'));
+    let stdout = run(&["-c", "green"], &[("CLICOLOR_FORCE", "1")]);
+    assert!(stdout.contains("\x1b[1;32m"));
+}